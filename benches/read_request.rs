@@ -0,0 +1,162 @@
+// Compares the buffered connection reader with the byte-at-a-time reader it replaced by sending
+// keep-alive requests over loopback to each
+
+use http::{Request, Response, Server, ServerBuilder, Status};
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+const REQUEST_COUNT: usize = 20_000;
+
+const REQUEST: &[u8] = b"POST /benchmark HTTP/1.1\r\n\
+Host: localhost\r\n\
+User-Agent: read_request-bench\r\n\
+Accept: */*\r\n\
+Connection: keep-alive\r\n\
+Content-Type: application/json\r\n\
+Content-Length: 27\r\n\
+\r\n\
+{\"key\":\"value\",\"number\":42}";
+
+struct BenchServer;
+
+impl Server for BenchServer {
+    fn handle_request(&self, _: Request) -> Response {
+        Response::new_status(Status::Ok, Some("ok".to_owned()))
+    }
+}
+
+static SERVER: BenchServer = BenchServer;
+
+fn main() {
+    let legacy = run_legacy_server();
+    let buffered = run_buffered_server();
+
+    let legacy_time = time_requests(legacy);
+    let buffered_time = time_requests(buffered);
+
+    report("byte-at-a-time", legacy_time);
+    report("buffered", buffered_time);
+    println!(
+        "speedup: {:.2}x",
+        legacy_time.as_secs_f64() / buffered_time.as_secs_f64()
+    );
+}
+
+fn report(name: &str, time: Duration) {
+    println!(
+        "{:>16}: {:>10.2?} total, {:>8.2?} per request",
+        name,
+        time,
+        time / REQUEST_COUNT as u32
+    );
+}
+
+fn run_buffered_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let handle = ServerBuilder::new(&SERVER)
+        .listener(listener)
+        .start()
+        .unwrap();
+    let addr = handle.local_addrs()[0];
+
+    // The server runs until the benchmark exits
+    thread::spawn(move || handle.join());
+
+    addr
+}
+
+fn run_legacy_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || while legacy_handle_request(&mut stream) {});
+        }
+    });
+
+    addr
+}
+
+// The reader used before buffering, one read call per byte
+fn legacy_handle_request(stream: &mut TcpStream) -> bool {
+    let mut buffer = Vec::with_capacity(128);
+
+    let mut char_buffer = [0];
+    loop {
+        if stream.read(&mut char_buffer).unwrap_or(0) == 0 {
+            return false;
+        }
+
+        buffer.push(char_buffer[0]);
+
+        if buffer.ends_with(b"\r\n\r\n") {
+            break;
+        }
+    }
+
+    let body_length = content_length(&buffer);
+    let mut body = Vec::with_capacity(body_length);
+    while body.len() < body_length {
+        if stream.read(&mut char_buffer).unwrap_or(0) == 0 {
+            return false;
+        }
+
+        body.push(char_buffer[0]);
+    }
+
+    let response = Response::new_status(Status::Ok, Some("ok".to_owned()));
+    stream.write_all(response.generate().as_bytes()).is_ok()
+}
+
+fn time_requests(addr: SocketAddr) -> Duration {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_nodelay(true).unwrap();
+
+    let mut buffer = Vec::new();
+    let start = Instant::now();
+    for _ in 0..REQUEST_COUNT {
+        stream.write_all(REQUEST).unwrap();
+        read_response(&mut stream, &mut buffer);
+    }
+
+    start.elapsed()
+}
+
+fn read_response(stream: &mut TcpStream, buffer: &mut Vec<u8>) {
+    buffer.clear();
+
+    let mut chunk = [0; 1024];
+    loop {
+        if let Some(position) = find(buffer, b"\r\n\r\n") {
+            let length = position + 4 + content_length(&buffer[..position + 4]);
+            if buffer.len() >= length {
+                return;
+            }
+        }
+
+        let bytes_read = stream.read(&mut chunk).unwrap();
+        assert!(bytes_read > 0, "server closed the connection");
+        buffer.extend_from_slice(&chunk[..bytes_read]);
+    }
+}
+
+fn content_length(header: &[u8]) -> usize {
+    String::from_utf8_lossy(header)
+        .split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
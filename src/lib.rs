@@ -2,6 +2,18 @@ mod request;
 mod response;
 mod server;
 
-pub use request::{Method, Request, RequestParseError};
-pub use response::{Response, Status};
-pub use server::{start_server, HandleClientError, ReadError, Server};
+pub use request::{
+    Header as RequestHeader, Method, PeerCredentials, Request, RequestParseError, TlsInfo, Version,
+};
+pub use response::{BodyWriter, Response, Status, StreamingBody};
+#[cfg(feature = "tokio")]
+pub use server::{serve_async, spawn_async, start_async_server, AsyncServer, AsyncServerHandle};
+pub use server::{
+    serve_connection, spawn_server, start_server, start_server_with_config, Backend, ClientErrorFn,
+    ErrorContext, HandleClientError, LoadStats, OverflowPolicy, RateLimiter, ReadError, Server,
+    ServerBuilder, ServerConfig, ServerHandle,
+};
+#[cfg(feature = "test-util")]
+pub use server::{TestClient, TestRequest, TestResponse};
+#[cfg(feature = "tls")]
+pub use server::{TlsConfig, TlsError};
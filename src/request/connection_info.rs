@@ -0,0 +1,31 @@
+use std::net::SocketAddr;
+
+// The connection requests arrive on, captured once when it is accepted
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionInfo {
+    id: u64,
+    peer_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+}
+
+impl ConnectionInfo {
+    pub fn new(id: u64, peer_addr: Option<SocketAddr>, local_addr: Option<SocketAddr>) -> Self {
+        ConnectionInfo {
+            id,
+            peer_addr,
+            local_addr,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+}
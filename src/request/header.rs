@@ -1,9 +1,13 @@
-use super::method::{InvalidMethodError, Method};
+use super::{
+    method::{InvalidMethodError, Method},
+    version::Version,
+};
 use std::collections::HashMap;
 
 pub struct Header {
     method: Method,
     uri: String,
+    version: Version,
     headers: HashMap<String, String>,
 }
 
@@ -15,6 +19,7 @@ pub enum RequestParseError {
     InvalidMethod(InvalidMethodError),
     NoURI,
     InvalidHTTPVersion,
+    UnsupportedHTTPVersion,
     NoVersion,
     RequestLineTooLong,
 }
@@ -24,51 +29,72 @@ impl Header {
         let mut lines = str.as_ref().split("\r\n");
 
         // Parse request line
-        let (method, uri) = Header::parse_request_line(match lines.next() {
+        let (method, uri, version) = Header::parse_request_line(match lines.next() {
             Some(str) => str.trim(),
             None => return Err(RequestParseError::NoRequestLine),
         })?;
 
         // Parse headers
-        let mut headers = HashMap::new();
+        let mut headers: HashMap<String, String> = HashMap::new();
         loop {
             let line = match lines.next() {
                 Some(str) => str.trim(),
                 None => return Err(RequestParseError::InvalidEnding),
             };
 
-            if line == "" {
+            if line.is_empty() {
                 break;
             }
 
-            let mut parts = line.split(':');
-            let key = match parts.next() {
-                Some(str) => str.trim(),
-                None => return Err(RequestParseError::InvalidHeaderLine(line.to_owned())),
-            };
-
-            let mut value = match parts.next() {
-                Some(str) => str.to_owned(),
-                None => String::new(),
-            };
-            for part in parts {
-                value.push(':');
-                value.push_str(part);
+            // A repeated field is the same as one with all its values in a list
+            let (key, value) = Header::parse_field(line)?;
+            match headers.get_mut(&key) {
+                Some(existing) => {
+                    existing.push_str(", ");
+                    existing.push_str(&value);
+                }
+                None => {
+                    headers.insert(key, value);
+                }
             }
-
-            let value = value.trim().to_owned();
-
-            headers.insert(key.to_owned(), value);
         }
 
         Ok(Header {
             method,
             uri,
+            version,
             headers,
         })
     }
 
-    fn parse_request_line<S: AsRef<str>>(str: S) -> Result<(Method, String), RequestParseError> {
+    // Parses a "key: value" line, shared with chunked body trailers
+    pub fn parse_field(line: &str) -> Result<(String, String), RequestParseError> {
+        let mut parts = line.split(':');
+        // Whitespace before the colon isn't trimmed away, since a name like "Transfer-Encoding "
+        // may be read differently by another server on the way
+        let key = match parts.next() {
+            Some(str) if !str.is_empty() && !str.contains(|c: char| c.is_ascii_whitespace()) => str,
+            _ => return Err(RequestParseError::InvalidHeaderLine(line.to_owned())),
+        };
+
+        let mut value = match parts.next() {
+            Some(str) => str.to_owned(),
+            None => String::new(),
+        };
+        for part in parts {
+            value.push(':');
+            value.push_str(part);
+        }
+
+        let value = value.trim().to_owned();
+
+        // Header names are case-insensitive
+        Ok((key.to_ascii_lowercase(), value))
+    }
+
+    fn parse_request_line<S: AsRef<str>>(
+        str: S,
+    ) -> Result<(Method, String, Version), RequestParseError> {
         let mut parts = str.as_ref().split(' ');
 
         // Parse method
@@ -87,24 +113,22 @@ impl Header {
         };
 
         // Parse version
-        match parts.next() {
-            Some(str) => {
-                if str.trim() != "HTTP/1.1" {
-                    return Err(RequestParseError::InvalidHTTPVersion);
-                }
-            }
+        let version = match parts.next() {
+            Some(str) => Version::parse(str.trim())?,
             None => return Err(RequestParseError::NoVersion),
-        }
+        };
 
         // Verify end of line
         match parts.next() {
             Some(_) => Err(RequestParseError::RequestLineTooLong),
-            None => Ok((method, uri.to_owned())),
+            None => Ok((method, uri.to_owned(), version)),
         }
     }
 
     pub fn get_header<S: AsRef<str>>(&self, key: S) -> Option<&str> {
-        self.headers.get(key.as_ref()).map(|s| s.as_str())
+        self.headers
+            .get(&key.as_ref().to_ascii_lowercase())
+            .map(|s| s.as_str())
     }
 
     pub fn method(&self) -> Method {
@@ -114,6 +138,10 @@ impl Header {
     pub fn uri(&self) -> &str {
         &self.uri
     }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
 }
 
 impl std::error::Error for RequestParseError {}
@@ -124,15 +152,16 @@ impl std::fmt::Display for RequestParseError {
             f,
             "{}",
             match self {
-                RequestParseError::NoRequestLine => format!("No request line"),
-                RequestParseError::InvalidEnding => format!("Invalid request header ending"),
+                RequestParseError::NoRequestLine => "No request line".to_owned(),
+                RequestParseError::InvalidEnding => "Invalid request header ending".to_owned(),
                 RequestParseError::InvalidHeaderLine(line) =>
                     format!("Invalid header line ({})", line),
                 RequestParseError::InvalidMethod(error) => format!("{}", error),
-                RequestParseError::NoURI => format!("No URI"),
-                RequestParseError::InvalidHTTPVersion => format!("Invalid HTTP version"),
-                RequestParseError::NoVersion => format!("No version"),
-                RequestParseError::RequestLineTooLong => format!("Request line too long"),
+                RequestParseError::NoURI => "No URI".to_owned(),
+                RequestParseError::InvalidHTTPVersion => "Invalid HTTP version".to_owned(),
+                RequestParseError::UnsupportedHTTPVersion => "Unsupported HTTP version".to_owned(),
+                RequestParseError::NoVersion => "No version".to_owned(),
+                RequestParseError::RequestLineTooLong => "Request line too long".to_owned(),
             }
         )
     }
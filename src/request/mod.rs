@@ -1,24 +1,105 @@
+use std::{collections::HashMap, net::SocketAddr};
+
+mod connection_info;
 mod header;
 mod method;
+mod peer_credentials;
+mod tls_info;
+mod version;
 
+pub(crate) use connection_info::ConnectionInfo;
 pub use header::{Header, RequestParseError};
 pub use method::Method;
+pub use peer_credentials::PeerCredentials;
+pub use tls_info::TlsInfo;
+pub use version::Version;
 
 pub struct Request {
     header: Header,
     body: String,
+    trailers: HashMap<String, String>,
+    tls: Option<TlsInfo>,
+    peer_credentials: Option<PeerCredentials>,
+    connection: ConnectionInfo,
+    request_index: usize,
 }
 
 impl Request {
     pub fn new(header: Header, body: String) -> Self {
-        Request { header, body }
+        Request::with_trailers(header, body, HashMap::new())
+    }
+
+    pub fn with_trailers(header: Header, body: String, trailers: HashMap<String, String>) -> Self {
+        Request {
+            header,
+            body,
+            trailers,
+            tls: None,
+            peer_credentials: None,
+            connection: ConnectionInfo::default(),
+            request_index: 0,
+        }
     }
 
     pub fn header(&self) -> &Header {
         &self.header
     }
 
+    pub fn version(&self) -> Version {
+        self.header.version()
+    }
+
     pub fn body(&self) -> &str {
         &self.body
     }
+
+    // Fields sent after a chunked body
+    pub fn get_trailer<S: AsRef<str>>(&self, key: S) -> Option<&str> {
+        self.trailers
+            .get(&key.as_ref().to_ascii_lowercase())
+            .map(|s| s.as_str())
+    }
+
+    // Set for requests received over TCP
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.connection.peer_addr()
+    }
+
+    // The address of the listener the request arrived on, set for requests received over TCP
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.connection.local_addr()
+    }
+
+    // Unique among the connections of one server
+    pub fn connection_id(&self) -> u64 {
+        self.connection.id()
+    }
+
+    // Counts the requests before this one on the same connection
+    pub fn request_index(&self) -> usize {
+        self.request_index
+    }
+
+    pub(crate) fn set_connection(&mut self, connection: ConnectionInfo, request_index: usize) {
+        self.connection = connection;
+        self.request_index = request_index;
+    }
+
+    // Set for requests received over TLS
+    pub fn tls(&self) -> Option<&TlsInfo> {
+        self.tls.as_ref()
+    }
+
+    pub(crate) fn set_tls(&mut self, tls: Option<TlsInfo>) {
+        self.tls = tls;
+    }
+
+    // Set for requests received over a Unix socket on Linux
+    pub fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.peer_credentials
+    }
+
+    pub(crate) fn set_peer_credentials(&mut self, peer_credentials: Option<PeerCredentials>) {
+        self.peer_credentials = peer_credentials;
+    }
 }
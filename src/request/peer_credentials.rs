@@ -0,0 +1,27 @@
+// The process on the other end of a Unix socket, as recorded by the kernel when it connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    uid: u32,
+    gid: u32,
+    pid: u32,
+}
+
+impl PeerCredentials {
+    #[cfg(target_os = "linux")]
+    pub(crate) fn new(uid: u32, gid: u32, pid: u32) -> Self {
+        PeerCredentials { uid, gid, pid }
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    // 0 if the peer is in a different PID namespace
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+}
@@ -0,0 +1,37 @@
+// Details of the TLS session a request arrived on
+#[derive(Debug, Clone)]
+pub struct TlsInfo {
+    server_name: Option<String>,
+    alpn_protocol: Option<Vec<u8>>,
+    peer_certificates: Vec<Vec<u8>>,
+}
+
+impl TlsInfo {
+    #[cfg(feature = "tls")]
+    pub(crate) fn new(
+        server_name: Option<String>,
+        alpn_protocol: Option<Vec<u8>>,
+        peer_certificates: Vec<Vec<u8>>,
+    ) -> Self {
+        TlsInfo {
+            server_name,
+            alpn_protocol,
+            peer_certificates,
+        }
+    }
+
+    // The name the client asked for through SNI
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_deref()
+    }
+
+    // The verified client certificate chain in DER, starting with the client's own certificate.
+    // Empty unless client certificates are enabled and the client sent one.
+    pub fn peer_certificates(&self) -> &[Vec<u8>] {
+        &self.peer_certificates
+    }
+}
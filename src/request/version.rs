@@ -0,0 +1,47 @@
+use super::RequestParseError;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl Version {
+    pub fn parse<S: AsRef<str>>(str: S) -> Result<Self, RequestParseError> {
+        Ok(match str.as_ref() {
+            "HTTP/1.0" => Version::Http10,
+            "HTTP/1.1" => Version::Http11,
+            str => {
+                // Well-formed versions we don't speak get a 505 instead of a 400
+                let mut digits = match str.strip_prefix("HTTP/") {
+                    Some(number) => number.split('.'),
+                    None => return Err(RequestParseError::InvalidHTTPVersion),
+                };
+
+                let is_number = |part: Option<&str>| match part {
+                    Some(part) => !part.is_empty() && part.bytes().all(|c| c.is_ascii_digit()),
+                    None => false,
+                };
+
+                if is_number(digits.next()) && is_number(digits.next()) && digits.next().is_none() {
+                    return Err(RequestParseError::UnsupportedHTTPVersion);
+                }
+
+                return Err(RequestParseError::InvalidHTTPVersion);
+            }
+        })
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Version::Http10 => "HTTP/1.0",
+                Version::Http11 => "HTTP/1.1",
+            }
+        )
+    }
+}
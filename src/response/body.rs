@@ -0,0 +1,108 @@
+use std::io::Write;
+
+const CHUNK_SIZE: usize = 8 * 1024;
+
+type Producer = Box<dyn FnOnce(&mut BodyWriter) -> std::io::Result<()> + Send>;
+
+// A body written to the client as it is produced instead of being rendered up front
+pub struct StreamingBody {
+    producer: Producer,
+}
+
+// Writes a streaming body as chunks for HTTP/1.1 clients, or raw until the connection closes
+// for HTTP/1.0 clients
+pub struct BodyWriter<'a> {
+    stream: &'a mut dyn Write,
+    chunked: bool,
+    buffer: Vec<u8>,
+    trailers: Vec<(String, String)>,
+}
+
+impl StreamingBody {
+    pub fn new<F>(producer: F) -> Self
+    where
+        F: FnOnce(&mut BodyWriter) -> std::io::Result<()> + Send + 'static,
+    {
+        StreamingBody {
+            producer: Box::new(producer),
+        }
+    }
+
+    pub(super) fn write_to(self, stream: &mut dyn Write, chunked: bool) -> std::io::Result<()> {
+        let mut writer = BodyWriter {
+            stream,
+            chunked,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+            trailers: Vec::new(),
+        };
+
+        (self.producer)(&mut writer)?;
+        writer.finish()
+    }
+
+    // Runs the producer to completion, keeping whatever it wrote before any error
+    pub(super) fn collect(self) -> Vec<u8> {
+        let mut body = Vec::new();
+        self.write_to(&mut body, false).ok();
+        body
+    }
+}
+
+impl<'a> BodyWriter<'a> {
+    // Trailers are only sent to clients receiving a chunked body
+    pub fn set_trailer(&mut self, key: String, value: String) {
+        self.trailers.push((key, value));
+    }
+
+    pub fn is_chunked(&self) -> bool {
+        self.chunked
+    }
+
+    fn write_chunk(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        if self.chunked {
+            write!(self.stream, "{:X}\r\n", self.buffer.len())?;
+            self.stream.write_all(&self.buffer)?;
+            self.stream.write_all(b"\r\n")?;
+        } else {
+            self.stream.write_all(&self.buffer)?;
+        }
+
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        self.write_chunk()?;
+
+        if self.chunked {
+            self.stream.write_all(b"0\r\n")?;
+            for (key, value) in &self.trailers {
+                write!(self.stream, "{}: {}\r\n", key, value)?;
+            }
+            self.stream.write_all(b"\r\n")?;
+        }
+
+        self.stream.flush()
+    }
+}
+
+impl<'a> Write for BodyWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    // Sends everything written so far to the client as its own chunk
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_chunk()?;
+        self.stream.flush()
+    }
+}
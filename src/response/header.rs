@@ -25,12 +25,28 @@ impl Header {
         }
     }
 
+    // Header names are case-insensitive, so a name replaces any set before in another case while
+    // keeping the case it's written in
     pub fn insert_header(&mut self, key: String, value: String) {
+        self.remove_header(&key);
         self.headers.insert(key, value);
     }
 
+    pub fn remove_header<S: AsRef<str>>(&mut self, key: S) -> Option<String> {
+        let key = self.find_key(key.as_ref())?.to_owned();
+        self.headers.remove(&key)
+    }
+
     pub fn get_header<S: AsRef<str>>(&self, key: S) -> Option<&str> {
-        self.headers.get(key.as_ref()).map(|s| s.as_str())
+        let key = self.find_key(key.as_ref())?;
+        self.headers.get(key).map(|s| s.as_str())
+    }
+
+    fn find_key(&self, key: &str) -> Option<&str> {
+        self.headers
+            .keys()
+            .find(|existing| existing.eq_ignore_ascii_case(key))
+            .map(|s| s.as_str())
     }
 
     pub fn generate(self) -> String {
@@ -1,27 +1,43 @@
 use self::header::Header;
+use crate::Version;
+use std::io::Write;
 
+mod body;
 mod header;
 mod status;
 
+pub use body::{BodyWriter, StreamingBody};
 pub use status::Status;
 
 pub struct Response {
     header: Header,
-    body: Option<String>,
+    body: Body,
+}
+
+enum Body {
+    Full(Option<String>),
+    Stream(StreamingBody),
 }
 
 impl Response {
     pub fn new(status_code: usize, reason_phrase: String, body: Option<String>) -> Self {
         Response {
             header: Header::new(status_code, reason_phrase),
-            body,
+            body: Body::Full(body),
         }
     }
 
     pub fn new_status(status: Status, body: Option<String>) -> Self {
         Response {
             header: Header::new_status(status),
-            body,
+            body: Body::Full(body),
+        }
+    }
+
+    pub fn new_stream(status: Status, body: StreamingBody) -> Self {
+        Response {
+            header: Header::new_status(status),
+            body: Body::Stream(body),
         }
     }
 
@@ -33,38 +49,76 @@ impl Response {
         &mut self.header
     }
 
-    pub fn generate(mut self) -> String {
+    pub fn is_streaming(&self) -> bool {
+        matches!(self.body, Body::Stream(_))
+    }
+
+    // Streaming bodies are collected in full and sent with a Content-Length
+    pub fn generate(self) -> String {
+        let mut header = self.header;
+        let body = match self.body {
+            Body::Full(body) => body,
+            Body::Stream(body) => Some(String::from_utf8_lossy(&body.collect()).into_owned()),
+        };
+
         // Set Content-Length, Server, and Content-Type
-        self.header.insert_header(
-            format!("Content-Length"),
+        header.insert_header(
+            "Content-Length".to_owned(),
             format!(
                 "{}",
-                match &self.body {
+                match &body {
                     Some(body) => body.len(),
                     None => 0,
                 }
             ),
         );
 
-        match self.header.get_header("Content-Type") {
-            Some(_) => {}
-            None => self
-                .header
-                .insert_header(format!("Content-Type"), format!("text/plain")),
-        }
-
-        self.header
-            .insert_header(format!("Server"), format!("Hart/1.0.0"));
+        Response::insert_default_headers(&mut header);
 
         // Generate header
-        let mut response = self.header.generate();
+        let mut response = header.generate();
 
         // Append body
-        match self.body {
-            Some(body) => response.push_str(&body),
-            None => {}
+        if let Some(body) = body {
+            response.push_str(&body);
         }
 
         response
     }
+
+    pub(crate) fn write<W: Write>(self, stream: &mut W, version: Version) -> std::io::Result<()> {
+        let (mut header, body) = match self.body {
+            Body::Stream(body) => (self.header, body),
+            body => {
+                let response = Response {
+                    header: self.header,
+                    body,
+                };
+                stream.write_all(response.generate().as_bytes())?;
+                return stream.flush();
+            }
+        };
+
+        // HTTP/1.0 clients can't decode chunks, so their body ends when the connection closes
+        let chunked = version == Version::Http11;
+
+        header.remove_header("Content-Length");
+        if chunked {
+            header.insert_header("Transfer-Encoding".to_owned(), "chunked".to_owned());
+        }
+
+        Response::insert_default_headers(&mut header);
+
+        stream.write_all(header.generate().as_bytes())?;
+        body.write_to(stream, chunked)
+    }
+
+    fn insert_default_headers(header: &mut Header) {
+        match header.get_header("Content-Type") {
+            Some(_) => {}
+            None => header.insert_header("Content-Type".to_owned(), "text/plain".to_owned()),
+        }
+
+        header.insert_header("Server".to_owned(), "Hart/1.0.0".to_owned());
+    }
 }
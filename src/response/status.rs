@@ -33,6 +33,8 @@ pub enum Status {
     UnsupportedMediaType,
     RequestedRangeNotSatisfiable,
     ExpectationFailed,
+    TooManyRequests,
+    RequestHeaderFieldsTooLarge,
     InternalServerError,
     NotImplemented,
     BadGateway,
@@ -78,6 +80,8 @@ impl Status {
             Status::UnsupportedMediaType => 415,
             Status::RequestedRangeNotSatisfiable => 416,
             Status::ExpectationFailed => 417,
+            Status::TooManyRequests => 429,
+            Status::RequestHeaderFieldsTooLarge => 431,
             Status::InternalServerError => 500,
             Status::NotImplemented => 501,
             Status::BadGateway => 502,
@@ -123,6 +127,8 @@ impl Status {
             Status::UnsupportedMediaType => "Unsupported Media Type",
             Status::RequestedRangeNotSatisfiable => "Requested Range Not Satisfiable",
             Status::ExpectationFailed => "Expectation Failed",
+            Status::TooManyRequests => "Too Many Requests",
+            Status::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
             Status::InternalServerError => "Internal Server Error",
             Status::NotImplemented => "Not Implemented",
             Status::BadGateway => "Bad Gateway",
@@ -0,0 +1,287 @@
+use super::socket::Listener;
+use std::{
+    env,
+    ffi::{CString, OsStr, OsString},
+    io::ErrorKind,
+    net::TcpListener,
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::PermissionsExt,
+        io::{FromRawFd, RawFd},
+        net::UnixListener,
+        process::CommandExt,
+    },
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// Inherited sockets are numbered from just after stdin, stdout and stderr
+const LISTEN_FDS_START: RawFd = 3;
+
+const LISTEN_PID_PREFIX: &[u8] = b"LISTEN_PID=";
+
+// Set once the inherited sockets are taken, so they are never owned twice
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+// What the new process is executed with, prepared up front since it can't allocate
+struct Exec {
+    program: CString,
+    arg_pointers: Vec<*const libc::c_char>,
+    env_pointers: Vec<*const libc::c_char>,
+    // Where the digits of LISTEN_PID go once the id is known
+    pid_digits: *mut u8,
+    // Owned here so the pointers above stay valid
+    _args: Vec<CString>,
+    _env: Vec<CString>,
+    _listen_pid: Vec<u8>,
+}
+
+// The pointers only point into the strings Exec owns
+unsafe impl Send for Exec {}
+unsafe impl Sync for Exec {}
+
+// Takes the listening sockets passed through LISTEN_FDS by systemd socket activation or by
+// spawn_with_listeners, returning none if nothing was passed to this process. The variables are
+// left as they are, processes started later see a LISTEN_PID that isn't theirs.
+pub fn inherited_listeners() -> std::io::Result<Vec<Listener>> {
+    let (count, pid) = match (env::var("LISTEN_FDS"), env::var("LISTEN_PID")) {
+        (Ok(count), Ok(pid)) => (count, pid),
+        _ => return Ok(Vec::new()),
+    };
+
+    if pid.parse() != Ok(std::process::id()) || TAKEN.swap(true, Ordering::SeqCst) {
+        return Ok(Vec::new());
+    }
+
+    let count: RawFd = count.parse().map_err(|_| {
+        std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid LISTEN_FDS value {}", count),
+        )
+    })?;
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(adopt)
+        .collect()
+}
+
+fn adopt(fd: RawFd) -> std::io::Result<Listener> {
+    // Keep the socket from leaking into processes started later
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let socket_type = socket_option(fd, libc::SO_TYPE)?;
+    if socket_option(fd, libc::SO_ACCEPTCONN)? == 0 {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Inherited file descriptor {} is not listening", fd),
+        ));
+    }
+
+    let mut address: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut length = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockname(
+            fd,
+            &mut address as *mut libc::sockaddr_storage as *mut libc::sockaddr,
+            &mut length,
+        )
+    };
+    if result == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    match (address.ss_family as libc::c_int, socket_type) {
+        (libc::AF_INET, libc::SOCK_STREAM) | (libc::AF_INET6, libc::SOCK_STREAM) => {
+            Ok(Listener::Tcp(unsafe { TcpListener::from_raw_fd(fd) }))
+        }
+        (libc::AF_UNIX, libc::SOCK_STREAM) => {
+            Ok(Listener::Unix(unsafe { UnixListener::from_raw_fd(fd) }))
+        }
+        _ => Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Inherited file descriptor {} is not a stream socket", fd),
+        )),
+    }
+}
+
+fn socket_option(fd: RawFd, option: libc::c_int) -> std::io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut length = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            option,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut length,
+        )
+    };
+
+    match result {
+        -1 => Err(std::io::Error::last_os_error()),
+        _ => Ok(value),
+    }
+}
+
+// Starts command with fds passed through LISTEN_FDS and its own id in LISTEN_PID, so it can take
+// them with inherited_listeners. The command gets this process's environment with its own
+// changes on top, env_clear isn't honored.
+pub fn spawn_with_listeners(command: &mut Command, fds: &[RawFd]) -> std::io::Result<Child> {
+    command
+        .env("LISTEN_FDS", fds.len().to_string())
+        .env_remove("LISTEN_PID")
+        .env_remove("LISTEN_FDNAMES");
+
+    // The id of the new process is only known once it runs, so it is executed directly with
+    // LISTEN_PID filled in rather than by Command
+    let exec = Exec::new(command)?;
+
+    let fds = fds.to_vec();
+    let mut moved = Vec::with_capacity(fds.len());
+    let first_free = LISTEN_FDS_START + fds.len() as RawFd;
+
+    // Runs in the new process, where allocating isn't safe, so moved has its capacity up front
+    let renumber = move || {
+        // Copy every socket above the target range first, since a target may currently hold
+        // another socket. The copies close on exec.
+        moved.clear();
+        for &fd in &fds {
+            let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, first_free) };
+            if copy == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            moved.push(copy);
+        }
+
+        // dup2 clears close-on-exec on the target, so these are the ones inherited
+        for (target, &copy) in (LISTEN_FDS_START..).zip(&moved) {
+            if unsafe { libc::dup2(copy, target) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        exec.run()
+    };
+
+    unsafe {
+        command.pre_exec(renumber);
+    }
+
+    command.spawn()
+}
+
+impl Exec {
+    fn new(command: &Command) -> std::io::Result<Self> {
+        let program = find_program(command)?;
+
+        let mut args = vec![c_string(command.get_program())?];
+        for arg in command.get_args() {
+            args.push(c_string(arg)?);
+        }
+
+        let mut variables: Vec<(OsString, OsString)> = env::vars_os().collect();
+        for (key, value) in command.get_envs() {
+            variables.retain(|(name, _)| name != key);
+            if let Some(value) = value {
+                variables.push((key.to_owned(), value.to_owned()));
+            }
+        }
+
+        let mut env = Vec::with_capacity(variables.len());
+        for (key, value) in variables {
+            let mut variable = key.into_vec();
+            variable.push(b'=');
+            variable.extend(value.into_vec());
+            env.push(c_string(OsStr::from_bytes(&variable))?);
+        }
+
+        // Room for any process id and the terminating nul
+        let mut listen_pid = LISTEN_PID_PREFIX.to_vec();
+        listen_pid.resize(LISTEN_PID_PREFIX.len() + 21, 0);
+
+        let mut arg_pointers: Vec<_> = args.iter().map(|arg| arg.as_ptr()).collect();
+        arg_pointers.push(std::ptr::null());
+        let mut env_pointers: Vec<_> = env.iter().map(|variable| variable.as_ptr()).collect();
+        env_pointers.push(listen_pid.as_mut_ptr() as *const libc::c_char);
+        env_pointers.push(std::ptr::null());
+        let pid_digits = unsafe { listen_pid.as_mut_ptr().add(LISTEN_PID_PREFIX.len()) };
+
+        Ok(Exec {
+            program: c_string(program.as_os_str())?,
+            arg_pointers,
+            env_pointers,
+            pid_digits,
+            _args: args,
+            _env: env,
+            _listen_pid: listen_pid,
+        })
+    }
+
+    // Runs in the new process, only returning if exec fails
+    fn run(&self) -> std::io::Result<()> {
+        let mut pid = unsafe { libc::getpid() } as u32;
+        let mut digits = [0; 20];
+        let mut count = 0;
+        loop {
+            digits[count] = b'0' + (pid % 10) as u8;
+            count += 1;
+            pid /= 10;
+            if pid == 0 {
+                break;
+            }
+        }
+
+        // The buffer has room for 20 digits and the nul after them
+        for (index, &digit) in digits[..count].iter().rev().enumerate() {
+            unsafe { *self.pid_digits.add(index) = digit };
+        }
+        unsafe { *self.pid_digits.add(count) = 0 };
+
+        unsafe {
+            libc::execve(
+                self.program.as_ptr(),
+                self.arg_pointers.as_ptr(),
+                self.env_pointers.as_ptr(),
+            );
+        }
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+// Looks program up in PATH like Command would, unless it is a path already
+fn find_program(command: &Command) -> std::io::Result<PathBuf> {
+    let program = Path::new(command.get_program());
+    if program.as_os_str().as_bytes().contains(&b'/') {
+        return Ok(program.to_owned());
+    }
+
+    let path = command
+        .get_envs()
+        .find(|(key, _)| *key == "PATH")
+        .map_or_else(
+            || env::var_os("PATH"),
+            |(_, value)| value.map(OsStr::to_owned),
+        )
+        .unwrap_or_default();
+
+    env::split_paths(&path)
+        .map(|directory| directory.join(program))
+        .find(|candidate| match candidate.metadata() {
+            Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+            Err(_) => false,
+        })
+        .ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} not found in PATH", program.display()),
+            )
+        })
+}
+
+fn c_string(value: &OsStr) -> std::io::Result<CString> {
+    CString::new(value.as_bytes())
+        .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Command contains a nul byte"))
+}
@@ -0,0 +1,678 @@
+use super::{
+    check_expectation,
+    chunked::ChunkedDecoder,
+    error_response, finish_response, idle_timeout, next_keep_alive, overload_response,
+    panic_message, panic_response, queued_requests,
+    read::{self, BodyLength},
+    report_error, request_keep_alive, should_linger,
+    stats::LoadStats,
+    ClientErrorFn, ErrorCallback, ErrorContext, Expectation, HandleClientError, Next, ReadError,
+    ServerConfig, CONTINUE_RESPONSE, LINGER_TIMEOUT,
+};
+use crate::{
+    request::{self, ConnectionInfo},
+    Request, Response, Version,
+};
+use std::{
+    collections::HashMap,
+    future::{self, Future},
+    io::Write,
+    net::SocketAddr,
+    panic::{self, AssertUnwindSafe},
+    pin::{pin, Pin},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, watch, Notify},
+    task::JoinHandle,
+    time,
+};
+
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+const OUTPUT_CHUNK_SIZE: usize = 16 * 1024;
+
+// Bytes read past the current request, kept for the next one
+struct Input {
+    buffer: Vec<u8>,
+    total_read: u64,
+}
+
+// Resolves to the panic payload if the future panics, instead of unwinding through the task
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+// How far a server has got in shutting down
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Phase {
+    Serving,
+    // No new clients or requests, but those in flight finish
+    Draining,
+    // Connections still open are dropped
+    Closed,
+}
+
+// State shared by the acceptor and connection tasks of a server
+struct Shared {
+    phase: watch::Sender<Phase>,
+    open_connections: AtomicUsize,
+    shed_at_connection_limit: AtomicU64,
+    all_closed: Notify,
+}
+
+pub struct AsyncServerHandle {
+    shared: Arc<Shared>,
+    acceptor: JoinHandle<()>,
+    local_addr: SocketAddr,
+}
+
+// Collects what a streaming body writes into chunks for the connection task, blocking while it
+// still has one to send so a large or slow response never piles up in memory
+struct ChunkWriter {
+    chunk: Vec<u8>,
+    output: mpsc::Sender<Vec<u8>>,
+}
+
+pub trait AsyncServer: Send + Sync + 'static {
+    fn handle_request(&self, request: Request) -> impl Future<Output = Response> + Send;
+
+    // Called before the body of an "Expect: 100-continue" request is read, returning a response
+    // rejects the request without the client sending its body
+    fn check_continue(&self, _header: &request::Header) -> Option<Response> {
+        None
+    }
+}
+
+impl<S: AsyncServer> AsyncServer for &'static S {
+    fn handle_request(&self, request: Request) -> impl Future<Output = Response> + Send {
+        (**self).handle_request(request)
+    }
+
+    fn check_continue(&self, header: &request::Header) -> Option<Response> {
+        (**self).check_continue(header)
+    }
+}
+
+impl<S: AsyncServer> AsyncServer for Arc<S> {
+    fn handle_request(&self, request: Request) -> impl Future<Output = Response> + Send {
+        (**self).handle_request(request)
+    }
+
+    fn check_continue(&self, header: &request::Header) -> Option<Response> {
+        (**self).check_continue(header)
+    }
+}
+
+pub async fn start_async_server<S: AsyncServer>(
+    port: u16,
+    server: S,
+    client_error_callback: Option<ClientErrorFn>,
+) -> Result<(), std::io::Error> {
+    let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port))).await?;
+    serve_async(
+        listener,
+        server,
+        &ServerConfig::new(),
+        client_error_callback,
+    )
+    .await
+}
+
+// Accepts clients forever, handling each connection in its own task. The worker pool and TLS
+// settings of config don't apply.
+pub async fn serve_async<S: AsyncServer>(
+    listener: TcpListener,
+    server: S,
+    config: &ServerConfig,
+    client_error_callback: Option<ClientErrorFn>,
+) -> Result<(), std::io::Error> {
+    check_config(config)?;
+
+    let shared = Arc::new(Shared::new());
+    accept_clients(
+        listener,
+        Arc::new(server),
+        config.clone(),
+        client_error_callback.map(Arc::from),
+        shared,
+    )
+    .await;
+    Ok(())
+}
+
+// Like serve_async, but accepts clients in a task of its own until the returned handle shuts the
+// server down. Must be called from within a Tokio runtime.
+pub fn spawn_async<S: AsyncServer>(
+    listener: TcpListener,
+    server: S,
+    config: &ServerConfig,
+    client_error_callback: Option<ClientErrorFn>,
+) -> Result<AsyncServerHandle, std::io::Error> {
+    check_config(config)?;
+
+    let local_addr = listener.local_addr()?;
+    let shared = Arc::new(Shared::new());
+    let acceptor = tokio::spawn(accept_clients(
+        listener,
+        Arc::new(server),
+        config.clone(),
+        client_error_callback.map(Arc::from),
+        shared.clone(),
+    ));
+
+    Ok(AsyncServerHandle {
+        shared,
+        acceptor,
+        local_addr,
+    })
+}
+
+impl AsyncServerHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // Connections are only shed at max_connections, there is no worker queue
+    pub fn load_stats(&self) -> LoadStats {
+        LoadStats::new(
+            self.shared.open_connections.load(Ordering::SeqCst),
+            self.shared.shed_at_connection_limit.load(Ordering::Relaxed),
+            0,
+        )
+    }
+
+    // Stops accepting, closes idle connections and waits up to drain_timeout for in-flight
+    // requests, dropping any connections still open after that
+    pub async fn shutdown(self, drain_timeout: Duration) {
+        self.shared.phase.send_replace(Phase::Draining);
+        self.acceptor.await.ok();
+
+        let deadline = time::Instant::now() + drain_timeout;
+        loop {
+            let mut all_closed = pin!(self.shared.all_closed.notified());
+            all_closed.as_mut().enable();
+            if self.shared.open_connections.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+
+            if time::timeout_at(deadline, all_closed).await.is_err() {
+                self.shared.phase.send_replace(Phase::Closed);
+                return;
+            }
+        }
+    }
+}
+
+impl Shared {
+    fn new() -> Self {
+        Shared {
+            phase: watch::Sender::new(Phase::Serving),
+            open_connections: AtomicUsize::new(0),
+            shed_at_connection_limit: AtomicU64::new(0),
+            all_closed: Notify::new(),
+        }
+    }
+
+    fn is_serving(&self) -> bool {
+        *self.phase.borrow() == Phase::Serving
+    }
+
+    fn close_connection(&self) {
+        if self.open_connections.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.all_closed.notify_waiters();
+        }
+    }
+}
+
+fn check_config(_config: &ServerConfig) -> Result<(), std::io::Error> {
+    #[cfg(feature = "tls")]
+    if _config.tls().is_some() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "TLS is only supported by the threaded backend",
+        ));
+    }
+
+    Ok(())
+}
+
+async fn accept_clients<S: AsyncServer>(
+    listener: TcpListener,
+    server: Arc<S>,
+    config: ServerConfig,
+    client_error_callback: ErrorCallback,
+    shared: Arc<Shared>,
+) {
+    let mut next_id = 0;
+    let mut phase = shared.phase.subscribe();
+
+    loop {
+        let accepted = match until(listener.accept(), &mut phase, Phase::Draining).await {
+            Some(accepted) => accepted,
+            None => return,
+        };
+
+        let mut stream = match accepted {
+            Ok((stream, _)) => stream,
+            Err(error) => {
+                report_error(
+                    &client_error_callback,
+                    HandleClientError::AcceptClientError(error),
+                    &ErrorContext::default(),
+                );
+                continue;
+            }
+        };
+
+        let config = config.clone();
+
+        // Clients beyond the limit are answered in a task of their own so accepting goes on
+        if shared.open_connections.fetch_add(1, Ordering::SeqCst) >= config.max_connections() {
+            shared.close_connection();
+            shared
+                .shed_at_connection_limit
+                .fetch_add(1, Ordering::Relaxed);
+            tokio::spawn(async move {
+                let response = overload_response(&config).generate();
+                stream.write_all(response.as_bytes()).await.ok();
+            });
+            continue;
+        }
+
+        let id = next_id;
+        next_id += 1;
+
+        let connection = ConnectionInfo::new(id, stream.peer_addr().ok(), stream.local_addr().ok());
+        let server = server.clone();
+        let config = config.clone();
+        let client_error_callback = client_error_callback.clone();
+        let shared = shared.clone();
+        tokio::spawn(async move {
+            let mut context = ErrorContext::new(connection);
+            let mut phase = shared.phase.subscribe();
+
+            // Panics the request loop doesn't catch take down the connection but not the runtime
+            // thread. Connections still open once the drain timeout passes are dropped.
+            let future = handle_client(stream, &*server, &config, &shared, &mut context);
+            let result = until(CatchUnwind(Box::pin(future)), &mut phase, Phase::Closed).await;
+
+            match result {
+                Some(Ok(Err(error))) => report_error(&client_error_callback, error, &context),
+                Some(Err(payload)) => report_error(
+                    &client_error_callback,
+                    HandleClientError::HandlerPanic(panic_message(payload)),
+                    &context,
+                ),
+                _ => {}
+            }
+            shared.close_connection();
+        });
+    }
+}
+
+// Runs future to completion, unless the server reaches phase first
+async fn until<F: Future>(
+    future: F,
+    phase: &mut watch::Receiver<Phase>,
+    until: Phase,
+) -> Option<F::Output> {
+    let mut future = pin!(future);
+    let mut reached = pin!(phase.wait_for(|phase| *phase >= until));
+
+    future::poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+
+        match reached.as_mut().poll(cx) {
+            Poll::Ready(_) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    })
+    .await
+}
+
+async fn handle_client<S: AsyncServer>(
+    mut stream: TcpStream,
+    server: &S,
+    config: &ServerConfig,
+    shared: &Shared,
+    context: &mut ErrorContext,
+) -> Result<(), HandleClientError> {
+    let mut input = Input {
+        buffer: Vec::new(),
+        total_read: 0,
+    };
+    let result = handle_connection(&mut stream, &mut input, server, config, shared, context).await;
+    context.set_bytes_read(input.total_read);
+
+    if stream.shutdown().await.is_ok() && should_linger(&result) {
+        linger(&mut stream).await;
+    }
+
+    result.map(|_| ())
+}
+
+// The threaded linger, on a tokio stream
+async fn linger(stream: &mut TcpStream) {
+    let mut buffer = [0; 1024];
+    time::timeout(LINGER_TIMEOUT, async {
+        while let Ok(1..) = stream.read(&mut buffer).await {}
+    })
+    .await
+    .ok();
+}
+
+async fn handle_connection<S: AsyncServer>(
+    stream: &mut TcpStream,
+    input: &mut Input,
+    server: &S,
+    config: &ServerConfig,
+    shared: &Shared,
+    context: &mut ErrorContext,
+) -> Result<Next, HandleClientError> {
+    let mut request_count = 0;
+    let mut pipelined_count = 0;
+    let mut phase = shared.phase.subscribe();
+
+    // Follows serve_requests of the threaded backend
+    while shared.is_serving() {
+        pipelined_count = queued_requests(pipelined_count, !input.buffer.is_empty());
+
+        // Wait for the next request, giving up on it once the server shuts down
+        if input.buffer.is_empty() {
+            let next = fill(stream, input, deadline(idle_timeout(config, request_count)));
+            match until(next, &mut phase, Phase::Draining).await {
+                None | Some(Ok(0)) | Some(Err(ReadError::Timeout)) => break,
+                Some(Ok(_)) => {}
+                Some(Err(error)) => return Err(HandleClientError::ReadRequestError(error)),
+            }
+        }
+
+        let request_index = request_count;
+        request_count += 1;
+
+        match handle_request(
+            stream,
+            input,
+            server,
+            config,
+            shared,
+            context,
+            request_index,
+            pipelined_count,
+        )
+        .await?
+        {
+            Next::Request => {}
+            Next::Close if !input.buffer.is_empty() => return Ok(Next::Linger),
+            next => return Ok(next),
+        }
+    }
+
+    Ok(Next::Close)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_request<S: AsyncServer>(
+    stream: &mut TcpStream,
+    input: &mut Input,
+    server: &S,
+    config: &ServerConfig,
+    shared: &Shared,
+    context: &mut ErrorContext,
+    request_index: usize,
+    pipelined_count: usize,
+) -> Result<Next, HandleClientError> {
+    // Follows handle_request of the threaded backend
+    context.clear_request_line();
+    let (header, body_length) = match read_header(stream, input, config).await {
+        Ok(Some(header)) => header,
+        Ok(None) => return Ok(Next::Close),
+        Err(error) => return Err(reject_request(stream, error).await),
+    };
+    context.set_request_line(&header);
+
+    match check_expectation(&header, &body_length, !input.buffer.is_empty(), |header| {
+        server.check_continue(header)
+    }) {
+        Expectation::Reject(response) => {
+            return write_response(stream, response, header.version(), config)
+                .await
+                .map(|()| Next::Linger);
+        }
+        Expectation::Continue => stream
+            .write_all(CONTINUE_RESPONSE)
+            .await
+            .map_err(HandleClientError::WriteResponseError)?,
+        Expectation::None => {}
+    }
+
+    let mut request = match read_body(stream, input, config, header, body_length).await {
+        Ok(request) => request,
+        Err(error) => return Err(reject_request(stream, error).await),
+    };
+    request.set_connection(context.connection(), request_index);
+    context.set_bytes_read(input.total_read);
+
+    let queued = queued_requests(pipelined_count, !input.buffer.is_empty());
+    let keep_alive = next_keep_alive(config, request_index + 1, queued);
+
+    let version = request.version();
+    let keep_alive = keep_alive && request_keep_alive(&request);
+    let (response, keep_alive, panicked) =
+        match CatchUnwind(Box::pin(server.handle_request(request))).await {
+            Ok(mut response) => {
+                // A server that began shutting down while the handler ran takes no more requests
+                let keep_alive = keep_alive && shared.is_serving();
+                let keep_alive = finish_response(&mut response, version, keep_alive);
+                (response, keep_alive, None)
+            }
+            Err(payload) => (panic_response(), false, Some(panic_message(payload))),
+        };
+
+    let result = write_response(stream, response, version, config).await;
+
+    match panicked {
+        Some(message) => Err(HandleClientError::HandlerPanic(message)),
+        None if keep_alive => result.map(|()| Next::Request),
+        None => result.map(|()| Next::Close),
+    }
+}
+
+async fn read_header(
+    stream: &mut TcpStream,
+    input: &mut Input,
+    config: &ServerConfig,
+) -> Result<Option<(request::Header, BodyLength)>, ReadError> {
+    let deadline = deadline(config.header_read_timeout());
+    loop {
+        if let Some((header, body_length, header_length)) =
+            read::parse_header(&input.buffer, config)?
+        {
+            input.buffer.drain(..header_length);
+            return Ok(Some((header, body_length)));
+        }
+
+        if fill(stream, input, deadline).await? == 0 {
+            return Ok(None);
+        }
+    }
+}
+
+async fn read_body(
+    stream: &mut TcpStream,
+    input: &mut Input,
+    config: &ServerConfig,
+    header: request::Header,
+    body_length: BodyLength,
+) -> Result<Request, ReadError> {
+    let deadline = deadline(config.body_read_timeout());
+
+    let (body, trailers) = match body_length {
+        BodyLength::Empty => (Vec::new(), HashMap::new()),
+        BodyLength::Fixed(body_length) => {
+            while input.buffer.len() < body_length {
+                if fill(stream, input, deadline).await? == 0 {
+                    return Err(ReadError::IncompleteBody);
+                }
+            }
+
+            (input.buffer.drain(..body_length).collect(), HashMap::new())
+        }
+        BodyLength::Chunked => {
+            let mut decoder = ChunkedDecoder::new();
+            loop {
+                let used = decoder.decode(&input.buffer, config)?;
+                input.buffer.drain(..used);
+                if decoder.is_done() {
+                    break decoder.into_parts();
+                }
+
+                if fill(stream, input, deadline).await? == 0 {
+                    return Err(ReadError::IncompleteChunkedBody);
+                }
+            }
+        }
+    };
+
+    read::build_request(header, body, trailers)
+}
+
+// Reads once into input, returning the number of bytes read
+async fn fill(
+    stream: &mut TcpStream,
+    input: &mut Input,
+    deadline: Option<Instant>,
+) -> Result<usize, ReadError> {
+    let mut buffer = [0; READ_CHUNK_SIZE];
+    let bytes_read = match deadline {
+        Some(deadline) => match time::timeout_at(deadline.into(), stream.read(&mut buffer)).await {
+            Ok(result) => result?,
+            Err(_) => return Err(ReadError::Timeout),
+        },
+        None => stream.read(&mut buffer).await?,
+    };
+
+    input.buffer.extend_from_slice(&buffer[..bytes_read]);
+    input.total_read += bytes_read as u64;
+    Ok(bytes_read)
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    response: Response,
+    version: Version,
+    config: &ServerConfig,
+) -> Result<(), HandleClientError> {
+    if !response.is_streaming() {
+        let mut output = Vec::new();
+        response
+            .write(&mut output, version)
+            .map_err(HandleClientError::WriteResponseError)?;
+        return write_output(stream, &output, config)
+            .await
+            .map_err(HandleClientError::WriteResponseError);
+    }
+
+    // Streaming bodies are produced by blocking code, so keep them off the runtime's threads and
+    // send each chunk as it is written
+    let (sender, mut chunks) = mpsc::channel(1);
+    let producer = tokio::task::spawn_blocking(move || {
+        let mut writer = ChunkWriter {
+            chunk: Vec::new(),
+            output: sender,
+        };
+        response.write(&mut writer, version)?;
+        writer.flush()
+    });
+
+    // Dropping the receiver on failure stops the producer at its next chunk
+    while let Some(chunk) = chunks.recv().await {
+        write_output(stream, &chunk, config)
+            .await
+            .map_err(HandleClientError::WriteResponseError)?;
+    }
+
+    // A panicking body counts as a panicking handler, as in the other backends
+    match producer.await {
+        Ok(result) => result.map_err(HandleClientError::WriteResponseError),
+        Err(error) if error.is_panic() => Err(HandleClientError::HandlerPanic(panic_message(
+            error.into_panic(),
+        ))),
+        Err(error) => Err(HandleClientError::WriteResponseError(
+            std::io::Error::other(error),
+        )),
+    }
+}
+
+async fn write_output(
+    stream: &mut TcpStream,
+    output: &[u8],
+    config: &ServerConfig,
+) -> std::io::Result<()> {
+    match config.write_timeout() {
+        Some(timeout) => match time::timeout(timeout, stream.write_all(output)).await {
+            Ok(result) => result,
+            Err(_) => Err(std::io::ErrorKind::TimedOut.into()),
+        },
+        None => stream.write_all(output).await,
+    }
+}
+
+// The threaded reject_request, on a tokio stream
+async fn reject_request(stream: &mut TcpStream, error: ReadError) -> HandleClientError {
+    stream
+        .write_all(error_response(&error).generate().as_bytes())
+        .await
+        .ok();
+
+    HandleClientError::ReadRequestError(error)
+}
+
+fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.map(|timeout| Instant::now() + timeout)
+}
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = std::thread::Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.0.as_mut();
+        match panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let length = buf.len().min(OUTPUT_CHUNK_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..length]);
+
+        if self.chunk.len() == OUTPUT_CHUNK_SIZE {
+            self.flush()?;
+        }
+
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+
+        // Fails once the connection task has given up on the response
+        let chunk = std::mem::take(&mut self.chunk);
+        self.output
+            .blocking_send(chunk)
+            .map_err(|_| std::io::ErrorKind::BrokenPipe.into())
+    }
+}
@@ -0,0 +1,148 @@
+use super::{
+    socket::{self, Listener},
+    ErrorCallback, ErrorContext, HandleClientError, Server, ServerConfig, ServerHandle,
+};
+use std::{
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+};
+#[cfg(unix)]
+use std::{os::unix::net::UnixListener, path::PathBuf};
+
+pub struct ServerBuilder<S: Server + 'static> {
+    server: Arc<S>,
+    config: ServerConfig,
+    addrs: Vec<SocketAddr>,
+    listeners: Vec<Listener>,
+    #[cfg(unix)]
+    socket_paths: Vec<PathBuf>,
+    #[cfg(unix)]
+    socket_mode: Option<u32>,
+    #[cfg(unix)]
+    inherit_listeners: bool,
+    client_error_callback: ErrorCallback,
+}
+
+impl<S: Server + 'static> ServerBuilder<S> {
+    // Takes the server by value, wrap it in an Arc or pass a reference to keep a hold of it too
+    pub fn new(server: S) -> Self {
+        ServerBuilder {
+            server: Arc::new(server),
+            config: ServerConfig::new(),
+            addrs: Vec::new(),
+            listeners: Vec::new(),
+            #[cfg(unix)]
+            socket_paths: Vec::new(),
+            #[cfg(unix)]
+            socket_mode: None,
+            #[cfg(unix)]
+            inherit_listeners: false,
+            client_error_callback: None,
+        }
+    }
+
+    // Binds a listener to addr when the server starts, "[::]" accepts both IPv4 and IPv6 clients
+    // on Unix
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.addrs.push(addr);
+        self
+    }
+
+    // Serves an already bound listener
+    pub fn listener(mut self, listener: TcpListener) -> Self {
+        self.listeners.push(Listener::Tcp(listener));
+        self
+    }
+
+    // Binds a Unix socket at path when the server starts, replacing a stale socket file left by
+    // a server that is no longer running. The file is removed again once the server stops.
+    #[cfg(unix)]
+    pub fn bind_unix<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.socket_paths.push(path.into());
+        self
+    }
+
+    // Permissions of socket files created by bind_unix, such as 0o660 to let only the owner and
+    // group connect. They are set before the file appears at its path. Defaults to what the
+    // process umask allows.
+    #[cfg(unix)]
+    pub fn unix_socket_mode(mut self, mode: u32) -> Self {
+        self.socket_mode = Some(mode);
+        self
+    }
+
+    // Serves an already bound Unix socket
+    #[cfg(unix)]
+    pub fn unix_listener(mut self, listener: UnixListener) -> Self {
+        self.listeners.push(Listener::Unix(listener));
+        self
+    }
+
+    // Serves sockets passed through LISTEN_FDS by systemd socket activation or by
+    // ServerHandle::spawn_replacement. When there are any they are served instead of binding, so
+    // the same setup works with and without them.
+    #[cfg(unix)]
+    pub fn inherit_listeners(mut self) -> Self {
+        self.inherit_listeners = true;
+        self
+    }
+
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Called from the serving threads with each client error and the connection it happened on
+    pub fn client_error_callback<F>(mut self, client_error_callback: F) -> Self
+    where
+        F: Fn(HandleClientError, &ErrorContext) + Send + Sync + 'static,
+    {
+        self.client_error_callback = Some(Arc::new(client_error_callback));
+        self
+    }
+
+    pub fn start(mut self) -> Result<ServerHandle, std::io::Error> {
+        let mut listeners = std::mem::take(&mut self.listeners);
+
+        #[cfg(unix)]
+        if self.inherit_listeners {
+            let inherited = super::activation::inherited_listeners()?;
+            if !inherited.is_empty() {
+                listeners.extend(inherited);
+                self.addrs.clear();
+                self.socket_paths.clear();
+            }
+        }
+
+        for addr in &self.addrs {
+            listeners.push(Listener::Tcp(socket::bind_tcp(*addr)?));
+        }
+        // Files bound so far are removed again if a later listener fails
+        #[cfg(unix)]
+        let mut socket_files = Vec::with_capacity(self.socket_paths.len());
+        #[cfg(unix)]
+        for path in &self.socket_paths {
+            let (listener, file) = super::unix::bind(path, self.socket_mode)?;
+            listeners.push(Listener::Unix(listener));
+            socket_files.push(file);
+        }
+
+        if listeners.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "No listeners to serve",
+            ));
+        }
+
+        let handle = super::serve_listeners(
+            listeners,
+            self.server,
+            &self.config,
+            self.client_error_callback,
+        )?;
+        #[cfg(unix)]
+        let handle = handle.remove_on_shutdown(socket_files);
+
+        Ok(handle)
+    }
+}
@@ -0,0 +1,188 @@
+use super::{reader::ConnectionReader, stream::Stream, ReadError, ServerConfig};
+use crate::request::Header;
+use std::{collections::HashMap, time::Instant};
+
+const MAX_CHUNK_LINE_LENGTH: usize = 4096;
+
+// Only "chunked" on its own is supported, since any other coding would have to be undone first
+pub fn check_transfer_encoding(value: &str) -> Result<(), ReadError> {
+    let codings: Vec<&str> = value
+        .split(',')
+        .map(|coding| coding.trim())
+        .filter(|coding| !coding.is_empty())
+        .collect();
+
+    match codings.last() {
+        Some(coding) if coding.eq_ignore_ascii_case("chunked") => {}
+        _ => return Err(ReadError::InvalidTransferEncoding(value.to_owned())),
+    }
+
+    match codings
+        .iter()
+        .find(|coding| !coding.eq_ignore_ascii_case("chunked"))
+    {
+        Some(coding) => Err(ReadError::UnsupportedTransferEncoding((*coding).to_owned())),
+        None if codings.len() > 1 => Err(ReadError::InvalidTransferEncoding(value.to_owned())),
+        None => Ok(()),
+    }
+}
+
+// Decodes a chunked body incrementally, so it can be fed bytes as they arrive
+pub struct ChunkedDecoder {
+    state: State,
+    body: Vec<u8>,
+    trailers: HashMap<String, String>,
+    trailer_size: usize,
+}
+
+enum State {
+    Size,
+    Data(usize),
+    DataEnd,
+    Trailers,
+    Done,
+}
+
+enum Line<'a> {
+    Complete(&'a [u8]),
+    TooLong,
+    Incomplete,
+}
+
+impl ChunkedDecoder {
+    pub fn new() -> Self {
+        ChunkedDecoder {
+            state: State::Size,
+            body: Vec::new(),
+            trailers: HashMap::new(),
+            trailer_size: 0,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    pub fn into_parts(self) -> (Vec<u8>, HashMap<String, String>) {
+        (self.body, self.trailers)
+    }
+
+    // Decodes as much of input as possible, returning how many bytes were used
+    pub fn decode(&mut self, input: &[u8], config: &ServerConfig) -> Result<usize, ReadError> {
+        let mut position = 0;
+
+        loop {
+            let rest = &input[position..];
+            match self.state {
+                State::Size => {
+                    // Parse "size[;extension...]", ignoring any extensions
+                    let line = match read_line(rest, MAX_CHUNK_LINE_LENGTH) {
+                        Line::Complete(line) => String::from_utf8(line.to_vec())?,
+                        Line::TooLong => return Err(ReadError::ChunkLineTooLong),
+                        Line::Incomplete => return Ok(position),
+                    };
+                    position += line.len() + 2;
+
+                    let size = line.split(';').next().unwrap_or("").trim();
+                    if size.is_empty() || !size.bytes().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(ReadError::InvalidChunkSize(line));
+                    }
+
+                    let size = match usize::from_str_radix(size, 16) {
+                        Ok(size) => size,
+                        Err(_) => return Err(ReadError::BodyTooLarge),
+                    };
+
+                    if size > config.max_body_size() - self.body.len() {
+                        return Err(ReadError::BodyTooLarge);
+                    }
+
+                    self.state = match size {
+                        0 => State::Trailers,
+                        size => State::Data(size),
+                    };
+                }
+                State::Data(remaining) => {
+                    if rest.is_empty() {
+                        return Ok(position);
+                    }
+
+                    let length = remaining.min(rest.len());
+                    self.body.extend_from_slice(&rest[..length]);
+                    position += length;
+
+                    self.state = match remaining - length {
+                        0 => State::DataEnd,
+                        remaining => State::Data(remaining),
+                    };
+                }
+                State::DataEnd => {
+                    // Chunk data must be followed by "\r\n"
+                    match read_line(rest, 0) {
+                        Line::Complete(_) => position += 2,
+                        Line::TooLong => return Err(ReadError::InvalidChunkEnding),
+                        Line::Incomplete => return Ok(position),
+                    }
+
+                    self.state = State::Size;
+                }
+                State::Trailers => {
+                    let remaining = config.max_header_size().saturating_sub(self.trailer_size);
+                    let line = match read_line(rest, remaining) {
+                        Line::Complete(line) => line,
+                        Line::TooLong => return Err(ReadError::HeaderTooLarge),
+                        Line::Incomplete => return Ok(position),
+                    };
+                    position += line.len() + 2;
+
+                    if line.is_empty() {
+                        self.state = State::Done;
+                        continue;
+                    }
+
+                    self.trailer_size += line.len() + 2;
+                    if self.trailers.len() >= config.max_header_count() {
+                        return Err(ReadError::TooManyHeaders);
+                    }
+
+                    let line = String::from_utf8(line.to_vec())?;
+                    let (key, value) = Header::parse_field(line.trim())?;
+                    self.trailers.insert(key, value);
+                }
+                State::Done => return Ok(position),
+            }
+        }
+    }
+}
+
+pub fn read_chunked_body<T: Stream>(
+    stream: &mut T,
+    reader: &mut ConnectionReader,
+    config: &ServerConfig,
+    deadline: Option<Instant>,
+) -> Result<(Vec<u8>, HashMap<String, String>), ReadError> {
+    let mut decoder = ChunkedDecoder::new();
+
+    loop {
+        let used = decoder.decode(reader.buffered(), config)?;
+        reader.consume(used);
+
+        if decoder.is_done() {
+            return Ok(decoder.into_parts());
+        }
+
+        if reader.fill(stream, deadline)? == 0 {
+            return Err(ReadError::IncompleteChunkedBody);
+        }
+    }
+}
+
+// Finds a line ending in "\r\n" at the start of input
+fn read_line(input: &[u8], max_length: usize) -> Line<'_> {
+    match input.windows(2).position(|window| window == b"\r\n") {
+        Some(length) if length > max_length => Line::TooLong,
+        Some(length) => Line::Complete(&input[..length]),
+        None if input.len() > max_length.saturating_add(1) => Line::TooLong,
+        None => Line::Incomplete,
+    }
+}
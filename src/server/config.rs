@@ -0,0 +1,265 @@
+#[cfg(feature = "tls")]
+use super::tls::{TlsConfig, TlsError};
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    Reject,
+    Block,
+}
+
+// Epoll serves every connection from one thread and hands finished requests to the workers, it is
+// only available on Linux
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Threaded,
+    Epoll,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    backend: Backend,
+    worker_count: usize,
+    queue_depth: usize,
+    overflow_policy: OverflowPolicy,
+    max_connections: usize,
+    retry_after: Duration,
+    header_read_timeout: Option<Duration>,
+    body_read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    max_request_line_length: usize,
+    max_header_size: usize,
+    max_header_count: usize,
+    max_body_size: usize,
+    max_requests_per_connection: usize,
+    max_pipelined_requests: usize,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+const DEFAULT_WORKER_COUNT: usize = 16;
+const DEFAULT_QUEUE_DEPTH: usize = 64;
+const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+const DEFAULT_HEADER_READ_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_BODY_READ_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_REQUEST_LINE_LENGTH: usize = 8 * 1024;
+const DEFAULT_MAX_HEADER_SIZE: usize = 32 * 1024;
+const DEFAULT_MAX_HEADER_COUNT: usize = 100;
+const DEFAULT_MAX_BODY_SIZE: usize = 8 * 1024 * 1024;
+const DEFAULT_MAX_REQUESTS_PER_CONNECTION: usize = 1000;
+const DEFAULT_MAX_PIPELINED_REQUESTS: usize = 32;
+
+impl ServerConfig {
+    pub fn new() -> Self {
+        ServerConfig {
+            backend: Backend::Threaded,
+            worker_count: DEFAULT_WORKER_COUNT,
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            overflow_policy: OverflowPolicy::Reject,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            retry_after: DEFAULT_RETRY_AFTER,
+            header_read_timeout: Some(DEFAULT_HEADER_READ_TIMEOUT),
+            body_read_timeout: Some(DEFAULT_BODY_READ_TIMEOUT),
+            write_timeout: Some(DEFAULT_WRITE_TIMEOUT),
+            keep_alive_timeout: Some(DEFAULT_KEEP_ALIVE_TIMEOUT),
+            max_request_line_length: DEFAULT_MAX_REQUEST_LINE_LENGTH,
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            max_header_count: DEFAULT_MAX_HEADER_COUNT,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_requests_per_connection: DEFAULT_MAX_REQUESTS_PER_CONNECTION,
+            max_pipelined_requests: DEFAULT_MAX_PIPELINED_REQUESTS,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    // The threaded backend holds a worker for as long as a connection stays open, idle time
+    // between keep-alive requests included, so this is also how many clients it serves at once.
+    // With persistent clients, size it for the clients expected, shorten the keep-alive timeout
+    // or use the epoll backend, whose workers are only busy while a handler runs.
+    pub fn worker_count(&self) -> usize {
+        self.worker_count
+    }
+
+    // Clients waiting for a worker in the threaded backend, or requests waiting for one in the
+    // epoll backend
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    // What happens once the queue is full, Reject answers with 503 and Block waits for room
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    // Counts connections waiting for a worker as well as those being served, clients beyond it
+    // are answered with 503 and closed right away
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    // Sent as Retry-After, rounded up to whole seconds, to clients turned away under load
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+
+    pub fn header_read_timeout(&self) -> Option<Duration> {
+        self.header_read_timeout
+    }
+
+    pub fn body_read_timeout(&self) -> Option<Duration> {
+        self.body_read_timeout
+    }
+
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+
+    pub fn keep_alive_timeout(&self) -> Option<Duration> {
+        self.keep_alive_timeout
+    }
+
+    pub fn max_request_line_length(&self) -> usize {
+        self.max_request_line_length
+    }
+
+    // Limits the header lines following the request line
+    pub fn max_header_size(&self) -> usize {
+        self.max_header_size
+    }
+
+    pub fn max_header_count(&self) -> usize {
+        self.max_header_count
+    }
+
+    pub fn max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+
+    pub fn max_requests_per_connection(&self) -> usize {
+        self.max_requests_per_connection
+    }
+
+    // Limits how many requests may be queued behind the one being answered, the connection is
+    // closed after answering the last request within the limit. Requests sent one at a time
+    // aren't queued, so 0 turns off pipelining but not keep-alive.
+    pub fn max_pipelined_requests(&self) -> usize {
+        self.max_pipelined_requests
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) -> &mut Self {
+        self.backend = backend;
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    pub(super) fn tls(&self) -> Option<&Arc<rustls::ServerConfig>> {
+        self.tls.as_ref()
+    }
+
+    pub fn set_worker_count(&mut self, worker_count: usize) -> &mut Self {
+        self.worker_count = worker_count.max(1);
+        self
+    }
+
+    pub fn set_queue_depth(&mut self, queue_depth: usize) -> &mut Self {
+        self.queue_depth = queue_depth;
+        self
+    }
+
+    pub fn set_overflow_policy(&mut self, overflow_policy: OverflowPolicy) -> &mut Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    pub fn set_max_connections(&mut self, max_connections: usize) -> &mut Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    pub fn set_retry_after(&mut self, retry_after: Duration) -> &mut Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    // A timeout of None or zero waits forever
+    pub fn set_header_read_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.header_read_timeout = non_zero(timeout);
+        self
+    }
+
+    pub fn set_body_read_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.body_read_timeout = non_zero(timeout);
+        self
+    }
+
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.write_timeout = non_zero(timeout);
+        self
+    }
+
+    pub fn set_keep_alive_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.keep_alive_timeout = non_zero(timeout);
+        self
+    }
+
+    pub fn set_max_request_line_length(&mut self, max_request_line_length: usize) -> &mut Self {
+        self.max_request_line_length = max_request_line_length;
+        self
+    }
+
+    pub fn set_max_header_size(&mut self, max_header_size: usize) -> &mut Self {
+        self.max_header_size = max_header_size;
+        self
+    }
+
+    pub fn set_max_header_count(&mut self, max_header_count: usize) -> &mut Self {
+        self.max_header_count = max_header_count;
+        self
+    }
+
+    pub fn set_max_body_size(&mut self, max_body_size: usize) -> &mut Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    pub fn set_max_requests_per_connection(
+        &mut self,
+        max_requests_per_connection: usize,
+    ) -> &mut Self {
+        self.max_requests_per_connection = max_requests_per_connection.max(1);
+        self
+    }
+
+    pub fn set_max_pipelined_requests(&mut self, max_pipelined_requests: usize) -> &mut Self {
+        self.max_pipelined_requests = max_pipelined_requests;
+        self
+    }
+
+    // Serves every connection over TLS, only the threaded backend supports it
+    #[cfg(feature = "tls")]
+    pub fn set_tls(&mut self, tls: &TlsConfig) -> Result<&mut Self, TlsError> {
+        self.tls = Some(tls.build()?);
+        Ok(self)
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig::new()
+    }
+}
+
+fn non_zero(timeout: Option<Duration>) -> Option<Duration> {
+    timeout.filter(|timeout| !timeout.is_zero())
+}
@@ -0,0 +1,983 @@
+use super::{
+    check_expectation,
+    chunked::ChunkedDecoder,
+    error_response, idle_timeout, next_keep_alive, overload_response, panic_message,
+    panic_response,
+    pool::WorkerPool,
+    queued_requests,
+    read::{self, BodyLength},
+    registry::{Registry, Shed},
+    report_error, respond, shed_client,
+    socket::Socket,
+    ErrorCallback, ErrorContext, Expectation, HandleClientError, Next, OverflowPolicy, ReadError,
+    Server, ServerConfig, ACCEPT_POLL_INTERVAL, CONTINUE_RESPONSE, LINGER_TIMEOUT,
+};
+use crate::{
+    request::{self, ConnectionInfo},
+    Request,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// Connections use their registry id as their token, listeners and the wake event sit far above
+const LISTENER_TOKEN: u64 = 1 << 63;
+const WAKE_TOKEN: u64 = u64::MAX;
+
+const READABLE: u32 = libc::EPOLLIN as u32;
+const WRITABLE: u32 = libc::EPOLLOUT as u32;
+const HANGUP: u32 = (libc::EPOLLHUP | libc::EPOLLERR) as u32;
+
+const MAX_EVENTS: usize = 1024;
+const MAX_ACCEPTS: usize = 128;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+const OUTPUT_CHUNK_SIZE: usize = 16 * 1024;
+const INPUT_LIMIT: usize = 64 * 1024;
+
+struct Reactor<S: Server + 'static> {
+    server: Arc<S>,
+    config: ServerConfig,
+    registry: Arc<Registry>,
+    client_error_callback: ErrorCallback,
+    poller: Poller,
+    listeners: Vec<TcpListener>,
+    // Listeners are left out of the poller until then after an accept error
+    accept_paused_until: Option<Instant>,
+    wake: Arc<File>,
+    connections: HashMap<u64, Connection>,
+    pool: WorkerPool<Job>,
+    backlog: VecDeque<Job>,
+    ready: Receiver<u64>,
+}
+
+struct Connection {
+    id: u64,
+    context: ErrorContext,
+    stream: TcpStream,
+    state: State,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    written: usize,
+    pending: Option<Pending>,
+    response: Option<Receiver<Output>>,
+    deadline: Option<Instant>,
+    started: bool,
+    closed: bool,
+    registered: bool,
+    interest: u32,
+    request_count: usize,
+    pipelined_count: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Reading,
+    Handling,
+    Writing { next: Next },
+    Closing,
+}
+
+// A request whose header has been parsed, waiting for the rest of its body
+struct Pending {
+    header: request::Header,
+    body: PendingBody,
+}
+
+enum PendingBody {
+    Empty,
+    Fixed(usize),
+    Chunked(ChunkedDecoder),
+}
+
+struct Job {
+    id: u64,
+    request: Request,
+    keep_alive: bool,
+    context: ErrorContext,
+    output: SyncSender<Output>,
+}
+
+// A response on its way from a worker, in chunks the reactor takes as the client reads them
+enum Output {
+    Data(Vec<u8>),
+    Done { keep_alive: bool },
+}
+
+// Tells the reactor a connection has output waiting
+#[derive(Clone)]
+struct Notifier {
+    ready: Sender<u64>,
+    wake: Arc<File>,
+}
+
+// Collects what a worker writes into chunks, blocking while the reactor still has one to send so
+// a large or slow response never piles up in memory
+struct ChunkWriter<'a> {
+    id: u64,
+    chunk: Vec<u8>,
+    sent: bool,
+    output: SyncSender<Output>,
+    notifier: &'a Notifier,
+}
+
+struct Poller {
+    fd: RawFd,
+}
+
+pub fn spawn_reactor<S: Server + 'static>(
+    listeners: Vec<TcpListener>,
+    server: Arc<S>,
+    config: &ServerConfig,
+    registry: &Arc<Registry>,
+    client_error_callback: ErrorCallback,
+) -> Result<JoinHandle<()>, std::io::Error> {
+    let poller = Poller::new()?;
+    for (index, listener) in listeners.iter().enumerate() {
+        poller.add(
+            listener.as_raw_fd(),
+            LISTENER_TOKEN + index as u64,
+            READABLE,
+        )?;
+    }
+
+    let wake = Arc::new(event_fd()?);
+    poller.add(wake.as_raw_fd(), WAKE_TOKEN, READABLE)?;
+    {
+        let wake = wake.clone();
+        registry.set_waker(move || {
+            (&*wake).write_all(&1u64.to_ne_bytes()).ok();
+        });
+    }
+
+    // Workers run the handler and hand the response back to the reactor as it is written
+    let (sender, ready) = mpsc::channel();
+    let pool = {
+        let server = server.clone();
+        let notifier = Notifier {
+            ready: sender,
+            wake: wake.clone(),
+        };
+        let client_error_callback = client_error_callback.clone();
+        WorkerPool::new(config.worker_count(), config.queue_depth(), move |job| {
+            run_job(&*server, job, &client_error_callback, &notifier)
+        })?
+    };
+
+    let mut reactor = Reactor {
+        server,
+        config: config.clone(),
+        registry: registry.clone(),
+        client_error_callback,
+        poller,
+        listeners,
+        accept_paused_until: None,
+        wake,
+        connections: HashMap::new(),
+        pool,
+        backlog: VecDeque::new(),
+        ready,
+    };
+
+    thread::Builder::new()
+        .name("http-reactor".to_owned())
+        .spawn(move || reactor.run())
+}
+
+fn run_job<S: Server>(
+    server: &S,
+    job: Job,
+    client_error_callback: &ErrorCallback,
+    notifier: &Notifier,
+) {
+    let Job {
+        id,
+        request,
+        keep_alive,
+        context,
+        output,
+    } = job;
+    let mut writer = ChunkWriter {
+        id,
+        chunk: Vec::new(),
+        sent: false,
+        output,
+        notifier,
+    };
+
+    let version = request.version();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let (response, version, keep_alive) = respond(server, request, keep_alive);
+        response.write(&mut writer, version).is_ok() && keep_alive
+    }));
+
+    // A panicking handler gets a 500 and the connection closes after it, the worker lives on. If
+    // part of the response already went out the client only sees it cut short.
+    let keep_alive = result.unwrap_or_else(|payload| {
+        let error = HandleClientError::HandlerPanic(panic_message(payload));
+        report_error(client_error_callback, error, &context);
+
+        if !writer.sent {
+            writer.chunk.clear();
+            panic_response().write(&mut writer, version).ok();
+        }
+        false
+    });
+
+    writer.finish(keep_alive);
+}
+
+impl ChunkWriter<'_> {
+    fn send(&mut self, output: Output) -> std::io::Result<()> {
+        // Fails once the reactor has closed the connection
+        if self.output.send(output).is_err() {
+            return Err(ErrorKind::BrokenPipe.into());
+        }
+
+        self.sent = true;
+        self.notifier.notify(self.id);
+        Ok(())
+    }
+
+    fn finish(mut self, keep_alive: bool) {
+        if self.flush().is_ok() {
+            self.send(Output::Done { keep_alive }).ok();
+        }
+    }
+}
+
+impl Write for ChunkWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let length = buf.len().min(OUTPUT_CHUNK_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..length]);
+
+        if self.chunk.len() == OUTPUT_CHUNK_SIZE {
+            self.flush()?;
+        }
+
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = std::mem::take(&mut self.chunk);
+        self.send(Output::Data(chunk))
+    }
+}
+
+impl Notifier {
+    fn notify(&self, id: u64) {
+        if self.ready.send(id).is_ok() {
+            (&*self.wake).write_all(&1u64.to_ne_bytes()).ok();
+        }
+    }
+}
+
+impl<S: Server> Reactor<S> {
+    fn run(&mut self) {
+        let mut events = vec![libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        let mut last_expiry = Instant::now();
+
+        loop {
+            // Keep serving open connections until they finish, like the threaded workers
+            if self.registry.is_shutting_down() {
+                self.stop_listening();
+                if self.connections.is_empty() {
+                    return;
+                }
+            }
+
+            let timeout = match self.accept_paused_until {
+                Some(until) => until
+                    .saturating_duration_since(Instant::now())
+                    .min(POLL_INTERVAL),
+                None => POLL_INTERVAL,
+            };
+
+            let count = match self.poller.wait(&mut events, timeout) {
+                Ok(count) => count,
+                Err(error) => {
+                    report_error(
+                        &self.client_error_callback,
+                        HandleClientError::AcceptClientError(error),
+                        &ErrorContext::default(),
+                    );
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+            };
+
+            for event in &events[..count] {
+                let (token, flags) = (event.u64, event.events);
+                if token == WAKE_TOKEN {
+                    // Reading resets the counter
+                    (&*self.wake).read_exact(&mut [0; 8]).ok();
+                } else if token >= LISTENER_TOKEN {
+                    self.accept((token - LISTENER_TOKEN) as usize);
+                } else {
+                    self.update(token, |reactor, connection| {
+                        reactor.on_ready(connection, flags)
+                    });
+                }
+            }
+
+            while let Ok(id) = self.ready.try_recv() {
+                self.update(id, |reactor, connection| reactor.pull(connection));
+            }
+
+            self.submit_backlog();
+
+            let now = Instant::now();
+            self.resume_accepting(now);
+            if now >= last_expiry + POLL_INTERVAL {
+                self.expire(now);
+                last_expiry = now;
+            }
+        }
+    }
+
+    fn stop_listening(&mut self) {
+        for listener in self.listeners.drain(..) {
+            self.poller.delete(listener.as_raw_fd());
+        }
+        self.accept_paused_until = None;
+    }
+
+    // Errors such as running out of file descriptors leave the client waiting and the listener
+    // ready, so stop watching the listeners for a while rather than spin on them
+    fn pause_accepting(&mut self) {
+        if self.accept_paused_until.is_none() {
+            for listener in &self.listeners {
+                self.poller.delete(listener.as_raw_fd());
+            }
+        }
+        self.accept_paused_until = Some(Instant::now() + ACCEPT_POLL_INTERVAL);
+    }
+
+    fn resume_accepting(&mut self, now: Instant) {
+        match self.accept_paused_until {
+            Some(until) if until <= now => self.accept_paused_until = None,
+            _ => return,
+        }
+
+        for (index, listener) in self.listeners.iter().enumerate() {
+            let token = LISTENER_TOKEN + index as u64;
+            if let Err(error) = self.poller.add(listener.as_raw_fd(), token, READABLE) {
+                report_error(
+                    &self.client_error_callback,
+                    HandleClientError::AcceptClientError(error),
+                    &ErrorContext::default(),
+                );
+            }
+        }
+    }
+
+    fn accept(&mut self, index: usize) {
+        for _ in 0..MAX_ACCEPTS {
+            // Listeners woken in the same round as an accept error wait as well
+            let result = match self.listeners.get(index) {
+                Some(_) if self.accept_paused_until.is_some() => return,
+                Some(listener) => listener.accept(),
+                None => return,
+            };
+
+            let result = match result {
+                Ok((stream, _)) => self.add_connection(stream),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return,
+                Err(error) => {
+                    self.pause_accepting();
+                    Err(error)
+                }
+            };
+
+            if let Err(error) = result {
+                report_error(
+                    &self.client_error_callback,
+                    HandleClientError::AcceptClientError(error),
+                    &ErrorContext::default(),
+                );
+                return;
+            }
+        }
+    }
+
+    fn add_connection(&mut self, stream: TcpStream) -> std::io::Result<()> {
+        stream.set_nonblocking(true)?;
+
+        let copy = Socket::Tcp(stream.try_clone()?);
+        let id = match self.registry.register(copy, self.config.max_connections()) {
+            Some(id) => id,
+            None => {
+                // The response fits in the empty send buffer, so writing doesn't block
+                self.registry.record_shed(Shed::ConnectionLimit);
+                shed_client(stream, &self.config);
+                return Ok(());
+            }
+        };
+        if let Err(error) = self.poller.add(stream.as_raw_fd(), id, READABLE) {
+            self.registry.unregister(id);
+            return Err(error);
+        }
+
+        let deadline = deadline(idle_timeout(&self.config, 0));
+        self.connections
+            .insert(id, Connection::new(id, stream, deadline));
+        Ok(())
+    }
+
+    // Runs f on a connection, closing it if f returns false
+    fn update<F: FnOnce(&mut Self, &mut Connection) -> bool>(&mut self, id: u64, f: F) {
+        let mut connection = match self.connections.remove(&id) {
+            Some(connection) => connection,
+            None => return,
+        };
+
+        if f(self, &mut connection) {
+            self.watch(&mut connection);
+            self.connections.insert(id, connection);
+        } else {
+            self.close(connection);
+        }
+    }
+
+    fn watch(&self, connection: &mut Connection) {
+        let interest = connection.wanted_interest();
+        if !connection.registered || interest == connection.interest {
+            return;
+        }
+
+        if self
+            .poller
+            .modify(connection.stream.as_raw_fd(), connection.id, interest)
+            .is_ok()
+        {
+            connection.interest = interest;
+        }
+    }
+
+    fn close(&mut self, connection: Connection) {
+        // The registry holds a copy of the socket, so it stays open until unregistered
+        if connection.registered {
+            self.poller.delete(connection.stream.as_raw_fd());
+        }
+
+        self.registry.unregister(connection.id);
+    }
+
+    fn on_ready(&mut self, connection: &mut Connection, flags: u32) -> bool {
+        if connection.state == State::Handling && flags & HANGUP != 0 {
+            // A failed socket keeps reporting until closed, stop watching it until the handler is
+            // done with the request
+            self.poller.delete(connection.stream.as_raw_fd());
+            connection.registered = false;
+            return true;
+        }
+
+        if connection.has_output() && !self.flush(connection) {
+            return false;
+        }
+
+        if flags & (READABLE | HANGUP) == 0 {
+            return true;
+        }
+
+        match connection.state {
+            State::Reading => {
+                let limit = self.input_limit(connection);
+                if let Err(error) = connection.fill(limit) {
+                    report_error(
+                        &self.client_error_callback,
+                        HandleClientError::ReadRequestError(error.into()),
+                        &connection.context,
+                    );
+                    return false;
+                }
+
+                self.advance(connection)
+            }
+            // Throw away whatever the client sends until it closes or the linger ends
+            State::Closing => {
+                let result = connection.fill(INPUT_LIMIT);
+                connection.input.clear();
+                result.is_ok() && !connection.closed
+            }
+            _ => true,
+        }
+    }
+
+    // Leaves room for the largest header allowed, or the whole of a fixed length body
+    fn input_limit(&self, connection: &Connection) -> usize {
+        let limit = match &connection.pending {
+            Some(Pending {
+                body: PendingBody::Fixed(length),
+                ..
+            }) => *length,
+            _ => self.config.max_request_line_length() + self.config.max_header_size() + 4,
+        };
+
+        limit.max(INPUT_LIMIT)
+    }
+
+    // Parses as much of the next request as has arrived, dispatching it once complete
+    fn advance(&mut self, connection: &mut Connection) -> bool {
+        if connection.pending.is_none() {
+            if connection.input.is_empty() {
+                return !connection.closed;
+            }
+
+            // The header timeout starts with the first byte of a request
+            if !connection.started {
+                connection.started = true;
+                connection.context.clear_request_line();
+                connection.deadline = deadline(self.config.header_read_timeout());
+                self.registry.set_idle(connection.id, false);
+            }
+
+            let (header, body_length, header_length) =
+                match read::parse_header(&connection.input, &self.config) {
+                    Ok(Some(header)) => header,
+                    Ok(None) => return !connection.closed,
+                    Err(error) => return self.reject(connection, error),
+                };
+            connection.input.drain(..header_length);
+            connection.context.set_request_line(&header);
+
+            let server = &self.server;
+            let body_started = !connection.input.is_empty();
+            match check_expectation(&header, &body_length, body_started, |header| {
+                server.check_continue(header)
+            }) {
+                Expectation::Reject(response) => {
+                    let mut output = Vec::new();
+                    response.write(&mut output, header.version()).ok();
+                    return self.send(connection, output, Next::Linger);
+                }
+                Expectation::Continue => {
+                    connection.output.extend_from_slice(CONTINUE_RESPONSE);
+                    if !self.flush(connection) {
+                        return false;
+                    }
+                }
+                Expectation::None => {}
+            }
+
+            connection.deadline = deadline(self.config.body_read_timeout());
+            connection.pending = Some(Pending {
+                header,
+                body: match body_length {
+                    BodyLength::Empty => PendingBody::Empty,
+                    BodyLength::Fixed(length) => PendingBody::Fixed(length),
+                    BodyLength::Chunked => PendingBody::Chunked(ChunkedDecoder::new()),
+                },
+            });
+        }
+
+        let (header, body, trailers) = match connection.take_request(&self.config) {
+            Ok(Some(request)) => request,
+            Ok(None) => return !connection.closed,
+            Err(error) => return self.reject(connection, error),
+        };
+
+        match read::build_request(header, body, trailers) {
+            Ok(request) => self.dispatch(connection, request),
+            Err(error) => self.reject(connection, error),
+        }
+    }
+
+    fn dispatch(&mut self, connection: &mut Connection, mut request: Request) -> bool {
+        request.set_connection(connection.context.connection(), connection.request_count);
+
+        connection.request_count += 1;
+        let queued = queued_requests(connection.pipelined_count, !connection.input.is_empty());
+        let keep_alive = next_keep_alive(&self.config, connection.request_count, queued)
+            && !self.registry.is_shutting_down();
+
+        connection.state = State::Handling;
+        connection.started = false;
+        connection.deadline = None;
+
+        let (output, response) = mpsc::sync_channel(1);
+        connection.response = Some(response);
+        let job = Job {
+            id: connection.id,
+            request,
+            keep_alive,
+            context: connection.context.clone(),
+            output,
+        };
+
+        // Jobs waiting for room in the queue stay in order behind each other
+        if !self.backlog.is_empty() && self.config.overflow_policy() == OverflowPolicy::Block {
+            self.backlog.push_back(job);
+            return true;
+        }
+
+        match self.pool.try_execute(job) {
+            Ok(()) => true,
+            Err(job) => match self.config.overflow_policy() {
+                OverflowPolicy::Block => {
+                    self.backlog.push_back(job);
+                    true
+                }
+                OverflowPolicy::Reject => {
+                    self.registry.record_shed(Shed::QueueLimit);
+                    connection.response = None;
+                    let response = overload_response(&self.config);
+                    self.send(connection, response.generate().into_bytes(), Next::Close)
+                }
+            },
+        }
+    }
+
+    fn submit_backlog(&mut self) {
+        while let Some(job) = self.backlog.pop_front() {
+            if let Err(job) = self.pool.try_execute(job) {
+                self.backlog.push_front(job);
+                return;
+            }
+        }
+    }
+
+    // The threaded reject_request, leaving the response to be written as the socket takes it
+    fn reject(&mut self, connection: &mut Connection, error: ReadError) -> bool {
+        let output = error_response(&error).generate().into_bytes();
+        report_error(
+            &self.client_error_callback,
+            HandleClientError::ReadRequestError(error),
+            &connection.context,
+        );
+
+        connection.pending = None;
+        self.send(connection, output, Next::Linger)
+    }
+
+    fn send(&mut self, connection: &mut Connection, output: Vec<u8>, next: Next) -> bool {
+        if !connection.registered {
+            return false;
+        }
+
+        if connection.output.is_empty() {
+            connection.output = output;
+        } else {
+            connection.output.extend_from_slice(&output);
+        }
+
+        connection.state = State::Writing { next };
+        connection.deadline = deadline(self.config.write_timeout());
+        self.flush(connection)
+    }
+
+    // Takes the next chunk of a response once the last one is written
+    fn pull(&mut self, connection: &mut Connection) -> bool {
+        if connection.has_output() {
+            return true;
+        }
+
+        let output = match &connection.response {
+            Some(response) => response.try_recv(),
+            None => return true,
+        };
+
+        match output {
+            Ok(Output::Data(output)) => {
+                if !connection.registered {
+                    return false;
+                }
+
+                connection.output = output;
+                connection.deadline = deadline(self.config.write_timeout());
+                self.flush(connection)
+            }
+            Ok(Output::Done { keep_alive }) => {
+                connection.response = None;
+                let next = if keep_alive {
+                    Next::Request
+                } else {
+                    Next::Close
+                };
+                self.send(connection, Vec::new(), next)
+            }
+            Err(TryRecvError::Empty) => true,
+            Err(TryRecvError::Disconnected) => false,
+        }
+    }
+
+    // Writes as much output as the socket takes, returning false if the connection failed
+    fn flush(&mut self, connection: &mut Connection) -> bool {
+        while connection.has_output() {
+            match (&connection.stream).write(&connection.output[connection.written..]) {
+                Ok(0) => return false,
+                Ok(written) => connection.written += written,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return true,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => {
+                    report_error(
+                        &self.client_error_callback,
+                        HandleClientError::WriteResponseError(error),
+                        &connection.context,
+                    );
+                    return false;
+                }
+            }
+        }
+
+        connection.output.clear();
+        connection.written = 0;
+
+        match connection.state {
+            State::Writing { next } => self.finish_response(connection, next),
+            // The handler may take its time producing the next chunk
+            State::Handling => {
+                connection.deadline = None;
+                self.pull(connection)
+            }
+            _ => true,
+        }
+    }
+
+    // Follows serve_requests of the threaded backend
+    fn finish_response(&mut self, connection: &mut Connection, next: Next) -> bool {
+        match next {
+            Next::Request if self.registry.set_idle(connection.id, true) => {}
+            Next::Request | Next::Close if connection.input.is_empty() => return false,
+            _ => return self.linger(connection),
+        }
+
+        let buffered = !connection.input.is_empty();
+        connection.pipelined_count = queued_requests(connection.pipelined_count, buffered);
+        if !buffered {
+            connection.deadline = deadline(idle_timeout(&self.config, connection.request_count));
+
+            // Idle connections shouldn't hold on to their buffers
+            connection.input.shrink_to_fit();
+            connection.output.shrink_to_fit();
+        }
+
+        connection.state = State::Reading;
+        self.advance(connection)
+    }
+
+    // The threaded linger, draining the input as it arrives
+    fn linger(&mut self, connection: &mut Connection) -> bool {
+        if connection.closed || connection.stream.shutdown(Shutdown::Write).is_err() {
+            return false;
+        }
+
+        connection.state = State::Closing;
+        connection.pending = None;
+        connection.input.clear();
+        connection.deadline = Some(Instant::now() + LINGER_TIMEOUT);
+        true
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let expired: Vec<u64> = self
+            .connections
+            .values()
+            .filter(|connection| matches!(connection.deadline, Some(deadline) if deadline <= now))
+            .map(|connection| connection.id)
+            .collect();
+
+        for id in expired {
+            self.update(id, |reactor, connection| match connection.state {
+                // Idle connections close quietly, partial requests get a 408
+                State::Reading if connection.started => {
+                    reactor.reject(connection, ReadError::Timeout)
+                }
+                // Deadlines while handling only cover writing the chunks of a response
+                State::Writing { .. } | State::Handling => {
+                    report_error(
+                        &reactor.client_error_callback,
+                        HandleClientError::WriteResponseError(ErrorKind::TimedOut.into()),
+                        &connection.context,
+                    );
+                    false
+                }
+                _ => false,
+            });
+        }
+    }
+}
+
+impl Connection {
+    fn new(id: u64, stream: TcpStream, deadline: Option<Instant>) -> Self {
+        Connection {
+            id,
+            context: ErrorContext::new(ConnectionInfo::new(
+                id,
+                stream.peer_addr().ok(),
+                stream.local_addr().ok(),
+            )),
+            stream,
+            state: State::Reading,
+            input: Vec::new(),
+            output: Vec::new(),
+            written: 0,
+            pending: None,
+            response: None,
+            deadline,
+            started: false,
+            closed: false,
+            registered: true,
+            interest: READABLE,
+            request_count: 0,
+            pipelined_count: 0,
+        }
+    }
+
+    fn has_output(&self) -> bool {
+        self.written < self.output.len()
+    }
+
+    fn wanted_interest(&self) -> u32 {
+        let output = if self.has_output() { WRITABLE } else { 0 };
+        match self.state {
+            State::Reading | State::Closing => READABLE | output,
+            State::Handling | State::Writing { .. } => output,
+        }
+    }
+
+    // Reads until the socket is drained, the client closes or the input reaches limit
+    fn fill(&mut self, limit: usize) -> std::io::Result<()> {
+        while self.input.len() < limit {
+            let length = self.input.len();
+            self.input.resize(length + READ_CHUNK_SIZE, 0);
+
+            let result = (&self.stream).read(&mut self.input[length..]);
+            let bytes_read = *result.as_ref().unwrap_or(&0);
+            self.input.truncate(length + bytes_read);
+            self.context
+                .set_bytes_read(self.context.bytes_read() + bytes_read as u64);
+
+            match result {
+                Ok(0) => {
+                    self.closed = true;
+                    return Ok(());
+                }
+                Ok(_) => {}
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Takes the pending request once its body has fully arrived
+    #[allow(clippy::type_complexity)]
+    fn take_request(
+        &mut self,
+        config: &ServerConfig,
+    ) -> Result<Option<(request::Header, Vec<u8>, HashMap<String, String>)>, ReadError> {
+        let pending = match &mut self.pending {
+            Some(pending) => pending,
+            None => return Ok(None),
+        };
+
+        let (body, trailers) = match &mut pending.body {
+            PendingBody::Empty => (Vec::new(), HashMap::new()),
+            PendingBody::Fixed(length) => {
+                if self.input.len() < *length {
+                    return Ok(None);
+                }
+
+                (self.input.drain(..*length).collect(), HashMap::new())
+            }
+            PendingBody::Chunked(decoder) => {
+                let used = decoder.decode(&self.input, config)?;
+                self.input.drain(..used);
+                if !decoder.is_done() {
+                    return Ok(None);
+                }
+
+                std::mem::replace(decoder, ChunkedDecoder::new()).into_parts()
+            }
+        };
+
+        Ok(self
+            .pending
+            .take()
+            .map(|pending| (pending.header, body, trailers)))
+    }
+}
+
+impl Poller {
+    fn new() -> std::io::Result<Self> {
+        let fd = check(unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) })?;
+        Ok(Poller { fd })
+    }
+
+    fn add(&self, fd: RawFd, token: u64, events: u32) -> std::io::Result<()> {
+        self.control(libc::EPOLL_CTL_ADD, fd, token, events)
+    }
+
+    fn modify(&self, fd: RawFd, token: u64, events: u32) -> std::io::Result<()> {
+        self.control(libc::EPOLL_CTL_MOD, fd, token, events)
+    }
+
+    fn delete(&self, fd: RawFd) {
+        self.control(libc::EPOLL_CTL_DEL, fd, 0, 0).ok();
+    }
+
+    fn control(&self, op: i32, fd: RawFd, token: u64, events: u32) -> std::io::Result<()> {
+        let mut event = libc::epoll_event { events, u64: token };
+        check(unsafe { libc::epoll_ctl(self.fd, op, fd, &mut event) })?;
+        Ok(())
+    }
+
+    fn wait(&self, events: &mut [libc::epoll_event], timeout: Duration) -> std::io::Result<usize> {
+        let count = unsafe {
+            libc::epoll_wait(
+                self.fd,
+                events.as_mut_ptr(),
+                events.len() as i32,
+                timeout.as_millis() as i32,
+            )
+        };
+
+        match check(count) {
+            Ok(count) => Ok(count as usize),
+            Err(error) if error.kind() == ErrorKind::Interrupted => Ok(0),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+// Lets workers wake the reactor when a response is ready
+fn event_fd() -> std::io::Result<File> {
+    let fd = check(unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) })?;
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn check(result: i32) -> std::io::Result<i32> {
+    if result < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.map(|timeout| Instant::now() + timeout)
+}
@@ -0,0 +1,86 @@
+use crate::request::{self, ConnectionInfo};
+use std::net::SocketAddr;
+
+// What was known about a connection when an error was reported. Errors that aren't tied to a
+// connection, such as failing to accept one, have no connection id or addresses, and clients that
+// failed before being given an id only have addresses.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorContext {
+    connection_id: Option<u64>,
+    peer_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+    request_line: Option<String>,
+    bytes_read: u64,
+}
+
+impl ErrorContext {
+    pub(super) fn new(connection: ConnectionInfo) -> Self {
+        ErrorContext {
+            connection_id: Some(connection.id()),
+            peer_addr: connection.peer_addr(),
+            local_addr: connection.local_addr(),
+            request_line: None,
+            bytes_read: 0,
+        }
+    }
+
+    // A client that was accepted but never given an id
+    pub(super) fn without_id(
+        peer_addr: Option<SocketAddr>,
+        local_addr: Option<SocketAddr>,
+    ) -> Self {
+        ErrorContext {
+            peer_addr,
+            local_addr,
+            ..ErrorContext::default()
+        }
+    }
+
+    pub fn connection_id(&self) -> Option<u64> {
+        self.connection_id
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    // The method, target and version of the request being handled, if its header was parsed
+    pub fn request_line(&self) -> Option<&str> {
+        self.request_line.as_deref()
+    }
+
+    // Everything read from the connection so far, across all of its requests
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    // Only used while serving requests, which always happens on a connection with an id
+    pub(super) fn connection(&self) -> ConnectionInfo {
+        ConnectionInfo::new(
+            self.connection_id.unwrap_or_default(),
+            self.peer_addr,
+            self.local_addr,
+        )
+    }
+
+    pub(super) fn set_request_line(&mut self, header: &request::Header) {
+        self.request_line = Some(format!(
+            "{} {} {}",
+            header.method(),
+            header.uri(),
+            header.version()
+        ));
+    }
+
+    pub(super) fn clear_request_line(&mut self) {
+        self.request_line = None;
+    }
+
+    pub(super) fn set_bytes_read(&mut self, bytes_read: u64) {
+        self.bytes_read = bytes_read;
+    }
+}
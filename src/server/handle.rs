@@ -0,0 +1,86 @@
+#[cfg(unix)]
+use super::unix::SocketFile;
+use super::{registry::Registry, stats::LoadStats};
+use std::{net::SocketAddr, sync::Arc, thread::JoinHandle, time::Duration};
+#[cfg(unix)]
+use std::{
+    os::unix::io::RawFd,
+    process::{Child, Command},
+};
+
+pub struct ServerHandle {
+    registry: Arc<Registry>,
+    acceptor: Option<JoinHandle<()>>,
+    local_addrs: Vec<SocketAddr>,
+    #[cfg(unix)]
+    listener_fds: Vec<RawFd>,
+}
+
+impl ServerHandle {
+    pub(super) fn new(
+        registry: Arc<Registry>,
+        acceptor: JoinHandle<()>,
+        local_addrs: Vec<SocketAddr>,
+        #[cfg(unix)] listener_fds: Vec<RawFd>,
+    ) -> Self {
+        ServerHandle {
+            registry,
+            acceptor: Some(acceptor),
+            local_addrs,
+            #[cfg(unix)]
+            listener_fds,
+        }
+    }
+
+    // Unix socket files to remove once the server stops, or once the handle is dropped after it
+    // has stopped
+    #[cfg(unix)]
+    pub(super) fn remove_on_shutdown(self, socket_files: Vec<SocketFile>) -> Self {
+        self.registry.set_socket_files(socket_files);
+        self
+    }
+
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    // Starts replacement with this server's listeners, which it takes through
+    // ServerBuilder::inherit_listeners. Both accept clients until this server is shut down, so a
+    // restart turns none away.
+    #[cfg(unix)]
+    pub fn spawn_replacement(&mut self, replacement: &mut Command) -> std::io::Result<Child> {
+        let child = super::activation::spawn_with_listeners(replacement, &self.listener_fds)?;
+
+        // The socket files belong to the replacement now
+        self.registry.disown_socket_files();
+        Ok(child)
+    }
+
+    pub fn load_stats(&self) -> LoadStats {
+        self.registry.stats()
+    }
+
+    // Blocks until the server stops
+    pub fn join(mut self) {
+        if let Some(acceptor) = self.acceptor.take() {
+            acceptor.join().ok();
+        }
+    }
+
+    // Stops accepting, waits up to drain_timeout for in-flight requests and joins the workers.
+    // Workers stuck in a handler past the deadline are left behind rather than joined.
+    pub fn shutdown(mut self, drain_timeout: Duration) {
+        self.registry.begin_shutdown();
+        let drained = self.registry.drain(drain_timeout);
+
+        // The acceptor joins the workers before it exits
+        if let Some(acceptor) = self.acceptor.take() {
+            if drained {
+                acceptor.join().ok();
+            }
+        }
+
+        #[cfg(unix)]
+        self.registry.remove_socket_files();
+    }
+}
@@ -1,18 +1,100 @@
-use crate::{Request, Response, Status};
+use crate::{
+    request::{self, ConnectionInfo},
+    Request, Response, Status, Version,
+};
+use pool::WorkerPool;
+use read::BodyLength;
+use reader::{start_deadline, ConnectionReader};
+use registry::{Registry, Shed};
+use socket::{Listener, Socket};
 use std::{
-    io::Write,
-    net::{TcpListener, TcpStream},
-    thread,
+    any::Any,
+    io::{ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream},
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
+use stream::{Stream, Transport};
 
+#[cfg(unix)]
+mod activation;
+#[cfg(feature = "tokio")]
+mod async_server;
+mod builder;
+mod chunked;
+mod config;
+#[cfg(target_os = "linux")]
+mod epoll;
+mod error_context;
+mod handle;
+mod pool;
+mod rate_limit;
 mod read;
+mod reader;
+mod registry;
+mod socket;
+mod stats;
+mod stream;
+#[cfg(feature = "test-util")]
+mod test_client;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+mod waiter;
 
+#[cfg(feature = "tokio")]
+pub use async_server::{
+    serve_async, spawn_async, start_async_server, AsyncServer, AsyncServerHandle,
+};
+pub use builder::ServerBuilder;
+pub use config::{Backend, OverflowPolicy, ServerConfig};
+pub use error_context::ErrorContext;
+pub use handle::ServerHandle;
+pub use rate_limit::RateLimiter;
 pub use read::ReadError;
+pub use stats::LoadStats;
+#[cfg(feature = "test-util")]
+pub use test_client::{TestClient, TestRequest, TestResponse};
+#[cfg(feature = "tls")]
+pub use tls::{TlsConfig, TlsError};
 
-pub type ClientErrorFn = fn(error: HandleClientError);
+pub type ClientErrorFn = Box<dyn Fn(HandleClientError, &ErrorContext) + Send + Sync>;
+
+// The callback as shared by the threads of a running server
+type ErrorCallback = Option<Arc<dyn Fn(HandleClientError, &ErrorContext) + Send + Sync>>;
 
 pub trait Server: Send + Sync {
     fn handle_request(&self, request: Request) -> Response;
+
+    // Called before the body of an "Expect: 100-continue" request is read, returning a response
+    // rejects the request without the client sending its body
+    fn check_continue(&self, _header: &request::Header) -> Option<Response> {
+        None
+    }
+}
+
+impl<S: Server + ?Sized> Server for &S {
+    fn handle_request(&self, request: Request) -> Response {
+        (**self).handle_request(request)
+    }
+
+    fn check_continue(&self, header: &request::Header) -> Option<Response> {
+        (**self).check_continue(header)
+    }
+}
+
+impl<S: Server + ?Sized> Server for Arc<S> {
+    fn handle_request(&self, request: Request) -> Response {
+        (**self).handle_request(request)
+    }
+
+    fn check_continue(&self, header: &request::Header) -> Option<Response> {
+        (**self).check_continue(header)
+    }
 }
 
 #[derive(Debug)]
@@ -20,84 +102,738 @@ pub enum HandleClientError {
     AcceptClientError(std::io::Error),
     ReadRequestError(ReadError),
     WriteResponseError(std::io::Error),
+    HandlerPanic(String),
 }
 
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const LINGER_TIMEOUT: Duration = Duration::from_millis(500);
+const CONTINUE_RESPONSE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+
 fn handle_client<S: Server>(
-    stream: Result<TcpStream, std::io::Error>,
+    socket: Socket,
+    id: u64,
     server: &S,
-) -> Result<(), HandleClientError> {
-    // Accept client
-    let mut stream = match stream {
-        Ok(stream) => stream,
-        Err(error) => return Err(HandleClientError::AcceptClientError(error)),
+    config: &ServerConfig,
+    registry: &Registry,
+    client_error_callback: &ErrorCallback,
+) {
+    let connection = ConnectionInfo::new(id, socket.peer_addr(), socket.local_addr());
+    let mut context = ErrorContext::new(connection);
+
+    // Panics the request loop doesn't catch, such as in a streaming body, take down the connection
+    // but not the worker
+    let result = if registry.is_shutting_down() {
+        Ok(())
+    } else {
+        panic::catch_unwind(AssertUnwindSafe(|| match socket {
+            Socket::Tcp(stream) => serve_tcp(stream, server, config, registry, &mut context),
+            #[cfg(unix)]
+            Socket::Unix(stream) => serve_stream(
+                &mut unix::UnixConnection::new(stream),
+                server,
+                config,
+                registry,
+                &mut context,
+            ),
+        }))
+        .unwrap_or_else(|payload| Err(HandleClientError::HandlerPanic(panic_message(payload))))
     };
 
-    // Handle requests until the connection closes
-    while handle_request(&mut stream, server)? {}
+    registry.unregister(id);
+    if let Err(error) = result {
+        report_error(client_error_callback, error, &context);
+    }
+}
+
+// Runs the request loop over any transport until the client stops sending requests, for
+// connections the server didn't accept itself such as in-memory pipes or custom TLS wrappers
+pub fn serve_connection<S: Server, T: Read + Write>(
+    stream: T,
+    server: &S,
+    config: &ServerConfig,
+) -> Result<(), HandleClientError> {
+    let mut stream = Transport(stream);
+
+    // Nothing else shuts this connection down, so it only needs a registry of its own
+    let registry = Registry::new();
+    let mut context = ErrorContext::new(ConnectionInfo::default());
+    let result = handle_connection(&mut stream, server, config, &registry, &mut context);
 
-    Ok(())
+    // The transport may buffer the last response
+    let flushed = stream
+        .flush()
+        .map_err(HandleClientError::WriteResponseError);
+    result.and(flushed).map(|_| ())
 }
 
-fn handle_request<S: Server>(
-    stream: &mut TcpStream,
+// TLS only applies to TCP clients
+fn serve_tcp<S: Server>(
+    mut stream: TcpStream,
     server: &S,
+    config: &ServerConfig,
+    registry: &Registry,
+    context: &mut ErrorContext,
+) -> Result<(), HandleClientError> {
+    #[cfg(feature = "tls")]
+    if let Some(tls) = config.tls() {
+        return match tls::accept(stream, tls, config) {
+            Ok(mut stream) => serve_stream(&mut stream, server, config, registry, context),
+            Err(error) => Err(HandleClientError::AcceptClientError(error)),
+        };
+    }
+
+    serve_stream(&mut stream, server, config, registry, context)
+}
+
+fn serve_stream<S: Server, T: Stream>(
+    stream: &mut T,
+    server: &S,
+    config: &ServerConfig,
+    registry: &Registry,
+    context: &mut ErrorContext,
+) -> Result<(), HandleClientError> {
+    let result = handle_connection(stream, server, config, registry, context);
+
+    // Shutting down writing also ends a TLS session cleanly
+    if stream.shutdown_write().is_ok() && should_linger(&result) {
+        linger(stream);
+    }
+
+    result.map(|_| ())
+}
+
+// Closing with unread input resets the connection, which can discard the last response before
+// the client reads it. Only rejected requests leave any, so only they hold up the close.
+fn should_linger(result: &Result<Next, HandleClientError>) -> bool {
+    match result {
+        Ok(next) => *next == Next::Linger,
+        Err(error) => matches!(error, HandleClientError::ReadRequestError(_)),
+    }
+}
+
+// Drains what the client still sends for a moment, so the close is clean
+fn linger<T: Stream>(stream: &mut T) {
+    let deadline = Instant::now() + LINGER_TIMEOUT;
+    let mut buffer = [0; 1024];
+    loop {
+        let now = Instant::now();
+        if now >= deadline || stream.set_read_timeout(Some(deadline - now)).is_err() {
+            return;
+        }
+
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+    }
+}
+
+fn handle_connection<S: Server, T: Stream>(
+    stream: &mut T,
+    server: &S,
+    config: &ServerConfig,
+    registry: &Registry,
+    context: &mut ErrorContext,
+) -> Result<Next, HandleClientError> {
+    let mut reader = ConnectionReader::new();
+    let result = serve_requests(stream, &mut reader, server, config, registry, context);
+    context.set_bytes_read(reader.total_read());
+    result
+}
+
+fn serve_requests<S: Server, T: Stream>(
+    stream: &mut T,
+    reader: &mut ConnectionReader,
+    server: &S,
+    config: &ServerConfig,
+    registry: &Registry,
+    context: &mut ErrorContext,
+) -> Result<Next, HandleClientError> {
+    stream
+        .set_write_timeout(config.write_timeout())
+        .map_err(HandleClientError::WriteResponseError)?;
+
+    let id = context.connection().id();
+    let mut request_count = 0;
+    let mut pipelined_count = 0;
+
+    // Handle requests until the connection closes or the server shuts down. Buffered requests
+    // are answered strictly in order, one at a time.
+    while registry.set_idle(id, true) {
+        pipelined_count = queued_requests(pipelined_count, !reader.is_empty());
+
+        // Wait for the next request
+        if !wait_for_request(stream, reader, idle_timeout(config, request_count))? {
+            break;
+        }
+
+        registry.set_idle(id, false);
+
+        let request_index = request_count;
+        request_count += 1;
+
+        match handle_request(
+            stream,
+            reader,
+            server,
+            config,
+            context,
+            request_index,
+            pipelined_count,
+            !registry.is_shutting_down(),
+        )? {
+            Next::Request => {}
+            // Requests the client already sent go unanswered
+            Next::Close if !reader.is_empty() => return Ok(Next::Linger),
+            next => return Ok(next),
+        }
+    }
+
+    Ok(Next::Close)
+}
+
+// Returns false if the client closed the connection or stayed idle too long
+fn wait_for_request<T: Stream>(
+    stream: &mut T,
+    reader: &mut ConnectionReader,
+    idle_timeout: Option<Duration>,
 ) -> Result<bool, HandleClientError> {
+    // A pipelined request may already be buffered
+    if !reader.is_empty() {
+        return Ok(true);
+    }
+
+    let result = match start_deadline(stream, idle_timeout) {
+        Ok(deadline) => reader.fill(stream, deadline),
+        Err(error) => Err(error),
+    };
+
+    match result {
+        Ok(bytes_read) => Ok(bytes_read > 0),
+        Err(ReadError::Timeout) => Ok(false),
+        Err(error) => Err(HandleClientError::ReadRequestError(error)),
+    }
+}
+
+// What happens to a connection after a request
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Next {
+    Request,
+    Close,
+    // Close, but the client may still be sending input that was never read
+    Linger,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_request<S: Server, T: Stream>(
+    stream: &mut T,
+    reader: &mut ConnectionReader,
+    server: &S,
+    config: &ServerConfig,
+    context: &mut ErrorContext,
+    request_index: usize,
+    pipelined_count: usize,
+    keep_alive: bool,
+) -> Result<Next, HandleClientError> {
     // Read request
-    let request = match read::read_request(stream) {
-        Ok(request) => match request {
-            Some(request) => request,
-            None => return Ok(false),
+    context.clear_request_line();
+    let (header, body_length) = match read::read_header(stream, reader, config) {
+        Ok(header) => match header {
+            Some(header) => header,
+            None => return Ok(Next::Close),
         },
-        Err(error) => {
-            // Create 400 response
-            let response = Response::new_status(Status::BadRequest, Some(format!("{}", error)));
-
-            // Send response
-            stream.write_all(response.generate().as_bytes()).ok();
+        Err(error) => return Err(reject_request(stream, error)),
+    };
+    context.set_request_line(&header);
 
-            return Err(HandleClientError::ReadRequestError(error));
+    match check_expectation(&header, &body_length, !reader.is_empty(), |header| {
+        server.check_continue(header)
+    }) {
+        Expectation::Reject(response) => {
+            return match response.write(stream, header.version()) {
+                Ok(()) => Ok(Next::Linger),
+                Err(error) => Err(HandleClientError::WriteResponseError(error)),
+            };
         }
-    };
+        Expectation::Continue => stream
+            .write_all(CONTINUE_RESPONSE)
+            .and_then(|()| stream.flush())
+            .map_err(HandleClientError::WriteResponseError)?,
+        Expectation::None => {}
+    }
 
-    let ret = match request.header().get_header("Connection") {
-        Some(str) => str == "keep-alive",
-        None => false,
+    let mut request = match read::read_body(stream, reader, config, header, body_length) {
+        Ok(request) => request,
+        Err(error) => return Err(reject_request(stream, error)),
     };
+    request.set_connection(context.connection(), request_index);
+    request.set_tls(stream.tls_info());
+    request.set_peer_credentials(stream.peer_credentials());
+
+    // Counted before the handler runs so a panic report has it too
+    context.set_bytes_read(reader.total_read());
+
+    let queued = queued_requests(pipelined_count, !reader.is_empty());
+    let keep_alive = keep_alive && next_keep_alive(config, request_index + 1, queued);
 
-    // Handle request
-    let response = server.handle_request(request);
+    // Handle request, a panicking handler gets a 500 and the connection closes after it
+    let version = request.version();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| respond(server, request, keep_alive)));
+    let (response, version, keep_alive, panicked) = match result {
+        Ok((response, version, keep_alive)) => (response, version, keep_alive, None),
+        Err(payload) => (
+            panic_response(),
+            version,
+            false,
+            Some(panic_message(payload)),
+        ),
+    };
 
     // Write response
-    match stream.write_all(response.generate().as_bytes()) {
-        Ok(()) => match stream.flush() {
-            Ok(()) => Ok(ret),
-            Err(error) => Err(HandleClientError::WriteResponseError(error)),
+    let result = response
+        .write(stream, version)
+        .map_err(HandleClientError::WriteResponseError);
+
+    match panicked {
+        Some(message) => Err(HandleClientError::HandlerPanic(message)),
+        None if keep_alive => result.map(|()| Next::Request),
+        None => result.map(|()| Next::Close),
+    }
+}
+
+// Runs the handler, returning the response and whether the connection can stay open after it
+fn respond<S: Server>(server: &S, request: Request, keep_alive: bool) -> (Response, Version, bool) {
+    let version = request.version();
+    let keep_alive = keep_alive && request_keep_alive(&request);
+
+    let mut response = server.handle_request(request);
+    let keep_alive = finish_response(&mut response, version, keep_alive);
+
+    (response, version, keep_alive)
+}
+
+// The first request gets the header timeout, later ones the keep-alive timeout
+fn idle_timeout(config: &ServerConfig, request_count: usize) -> Option<Duration> {
+    if request_count == 0 {
+        config.header_read_timeout()
+    } else {
+        config.keep_alive_timeout()
+    }
+}
+
+// Counts the requests queued behind a response, once the next request is already buffered. Those
+// only follow each other while the client keeps sending before it sees the previous response.
+fn queued_requests(pipelined_count: usize, buffered: bool) -> usize {
+    if buffered {
+        pipelined_count + 1
+    } else {
+        0
+    }
+}
+
+// Returns false once the client has sent too many requests in total, or has more queued behind
+// the response than allowed. The connection then closes before the first request over the limit.
+fn next_keep_alive(config: &ServerConfig, request_count: usize, queued: usize) -> bool {
+    request_count < config.max_requests_per_connection()
+        && queued <= config.max_pipelined_requests()
+}
+
+// Returns false if the request doesn't let the connection stay open after it
+fn request_keep_alive(request: &Request) -> bool {
+    // HTTP/1.1 connections persist unless either side sends "close", HTTP/1.0 ones only if the
+    // client asks for "keep-alive"
+    let header = request.header();
+    let connection = header.get_header("Connection").unwrap_or("");
+    let keep_alive = match header.version() {
+        Version::Http11 => !has_token(connection, "close"),
+        Version::Http10 => has_token(connection, "keep-alive") && !has_token(connection, "close"),
+    };
+
+    // A request framed by both Transfer-Encoding and Content-Length, or by Transfer-Encoding
+    // in HTTP/1.0, may have been meant to be read differently, so don't trust what follows it
+    let ambiguous = header.get_header("Transfer-Encoding").is_some()
+        && (header.version() == Version::Http10 || header.get_header("Content-Length").is_some());
+
+    keep_alive && !ambiguous
+}
+
+// Sets the Connection header, returning whether the connection can stay open after the response
+fn finish_response(response: &mut Response, version: Version, mut keep_alive: bool) -> bool {
+    if let Some(value) = response.header().get_header("Connection") {
+        keep_alive &= !has_token(value, "close");
+    }
+
+    // Streams to HTTP/1.0 clients end when the connection closes
+    if response.is_streaming() && version == Version::Http10 {
+        keep_alive = false;
+    }
+
+    if !keep_alive {
+        set_connection_close(response);
+    } else if version == Version::Http10 {
+        response
+            .header_mut()
+            .insert_header("Connection".to_owned(), "keep-alive".to_owned());
+    }
+
+    keep_alive
+}
+
+enum Expectation {
+    None,
+    // Send the interim response before reading the body
+    Continue,
+    // Send this response instead of reading the body. The client may still send the body, so no
+    // other request is read after it.
+    Reject(Response),
+}
+
+// Answers an "Expect" header before the body is read. The interim response is skipped if the
+// client already started sending the body.
+fn check_expectation<F: FnOnce(&request::Header) -> Option<Response>>(
+    header: &request::Header,
+    body_length: &BodyLength,
+    body_started: bool,
+    check_continue: F,
+) -> Expectation {
+    // HTTP/1.0 clients can't expect anything
+    let expect = match header.get_header("Expect") {
+        Some(expect) if header.version() == Version::Http11 => expect,
+        _ => return Expectation::None,
+    };
+
+    let response = if !expect.eq_ignore_ascii_case("100-continue") {
+        Some(Response::new_status(Status::ExpectationFailed, None))
+    } else {
+        check_continue(header)
+    };
+
+    if let Some(mut response) = response {
+        set_connection_close(&mut response);
+        return Expectation::Reject(response);
+    }
+
+    match body_length {
+        BodyLength::Empty => Expectation::None,
+        _ if body_started => Expectation::None,
+        _ => Expectation::Continue,
+    }
+}
+
+fn error_response(error: &ReadError) -> Response {
+    let mut response = Response::new_status(error.status(), Some(format!("{}", error)));
+    set_connection_close(&mut response);
+    response
+}
+
+// Sends the error response for a request that couldn't be read
+fn reject_request<T: Stream>(stream: &mut T, error: ReadError) -> HandleClientError {
+    stream
+        .write_all(error_response(&error).generate().as_bytes())
+        .ok();
+
+    HandleClientError::ReadRequestError(error)
+}
+
+// Checks a comma separated header value for a token, ignoring case
+fn has_token(value: &str, token: &str) -> bool {
+    value
+        .split(',')
+        .any(|part| part.trim().eq_ignore_ascii_case(token))
+}
+
+fn set_connection_close(response: &mut Response) {
+    response
+        .header_mut()
+        .insert_header("Connection".to_owned(), "close".to_owned());
+}
+
+// Answers a request whose handler panicked
+fn panic_response() -> Response {
+    let mut response = Response::new_status(Status::InternalServerError, None);
+    set_connection_close(&mut response);
+    response
+}
+
+// Tells a client turned away under load when to try again
+fn overload_response(config: &ServerConfig) -> Response {
+    let mut response = Response::new_status(Status::ServiceUnavailable, None);
+    response.header_mut().insert_header(
+        "Retry-After".to_owned(),
+        format!("{}", retry_after_seconds(config.retry_after())),
+    );
+    set_connection_close(&mut response);
+    response
+}
+
+// Rounds up, and never down to 0 which would have clients retry right away
+fn retry_after_seconds(retry_after: Duration) -> u64 {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    seconds.max(1)
+}
+
+// Answers without reading the request, since the point is to spend nothing on the client
+fn shed_client<W: Write>(mut stream: W, config: &ServerConfig) {
+    stream
+        .write_all(overload_response(config).generate().as_bytes())
+        .ok();
+}
+
+fn report_error(
+    client_error_callback: &ErrorCallback,
+    error: HandleClientError,
+    context: &ErrorContext,
+) {
+    if let Some(callback) = client_error_callback {
+        callback(error, context);
+    }
+}
+
+// Panics usually carry a message as a &str or a String
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast_ref::<&str>() {
+            Some(message) => (*message).to_owned(),
+            None => "Unknown panic payload".to_owned(),
         },
-        Err(error) => Err(HandleClientError::WriteResponseError(error)),
     }
 }
 
-pub fn start_server<S: Server>(
+pub fn start_server<S: Server + 'static>(
     port: u16,
-    server: &'static S,
+    server: S,
     client_error_callback: Option<ClientErrorFn>,
 ) -> Result<(), std::io::Error> {
-    let listener = TcpListener::bind(("0.0.0.0", port))?;
-
-    for stream in listener.incoming() {
-        let server = server;
-        thread::spawn(move || match handle_client(stream, server) {
-            Ok(()) => {}
-            Err(error) => match client_error_callback {
-                Some(callback) => callback(error),
-                None => {}
-            },
-        });
-    }
+    start_server_with_config(port, server, &ServerConfig::new(), client_error_callback)
+}
+
+pub fn start_server_with_config<S: Server + 'static>(
+    port: u16,
+    server: S,
+    config: &ServerConfig,
+    client_error_callback: Option<ClientErrorFn>,
+) -> Result<(), std::io::Error> {
+    spawn_server(port, server, config, client_error_callback)?.join();
 
     Ok(())
 }
 
+pub fn spawn_server<S: Server + 'static>(
+    port: u16,
+    server: S,
+    config: &ServerConfig,
+    client_error_callback: Option<ClientErrorFn>,
+) -> Result<ServerHandle, std::io::Error> {
+    let mut builder = ServerBuilder::new(server)
+        .bind(SocketAddr::from(([0, 0, 0, 0], port)))
+        .config(config.clone());
+
+    if let Some(callback) = client_error_callback {
+        builder = builder.client_error_callback(callback);
+    }
+
+    builder.start()
+}
+
+fn serve_listeners<S: Server + 'static>(
+    listeners: Vec<Listener>,
+    server: Arc<S>,
+    config: &ServerConfig,
+    client_error_callback: ErrorCallback,
+) -> Result<ServerHandle, std::io::Error> {
+    let mut local_addrs = Vec::with_capacity(listeners.len());
+    for listener in &listeners {
+        listener.set_nonblocking(true)?;
+        if let Listener::Tcp(listener) = listener {
+            local_addrs.push(listener.local_addr()?);
+        }
+    }
+
+    // Kept to hand the listeners to a replacement process, they stay open until the server stops
+    #[cfg(unix)]
+    let listener_fds = listeners.iter().map(Listener::as_raw_fd).collect();
+
+    #[cfg(feature = "tls")]
+    if config.tls().is_some() && config.backend() != Backend::Threaded {
+        return Err(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "TLS is only supported by the threaded backend",
+        ));
+    }
+
+    let registry = Arc::new(Registry::new());
+
+    let acceptor = match config.backend() {
+        Backend::Threaded => {
+            spawn_acceptor(listeners, server, config, &registry, client_error_callback)?
+        }
+        #[cfg(target_os = "linux")]
+        Backend::Epoll => {
+            let listeners = listeners
+                .into_iter()
+                .map(|listener| match listener {
+                    Listener::Tcp(listener) => Ok(listener),
+                    Listener::Unix(_) => Err(std::io::Error::new(
+                        ErrorKind::Unsupported,
+                        "Unix sockets are only supported by the threaded backend",
+                    )),
+                })
+                .collect::<Result<_, _>>()?;
+
+            epoll::spawn_reactor(listeners, server, config, &registry, client_error_callback)?
+        }
+        #[cfg(not(target_os = "linux"))]
+        Backend::Epoll => {
+            return Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "The epoll backend is only available on Linux",
+            ))
+        }
+    };
+
+    Ok(ServerHandle::new(
+        registry,
+        acceptor,
+        local_addrs,
+        #[cfg(unix)]
+        listener_fds,
+    ))
+}
+
+fn spawn_acceptor<S: Server + 'static>(
+    listeners: Vec<Listener>,
+    server: Arc<S>,
+    config: &ServerConfig,
+    registry: &Arc<Registry>,
+    client_error_callback: ErrorCallback,
+) -> Result<JoinHandle<()>, std::io::Error> {
+    let pool = {
+        let registry = registry.clone();
+        let config = config.clone();
+        let client_error_callback = client_error_callback.clone();
+        WorkerPool::new(
+            config.worker_count(),
+            config.queue_depth(),
+            move |(socket, id)| {
+                handle_client(
+                    socket,
+                    id,
+                    &*server,
+                    &config,
+                    &registry,
+                    &client_error_callback,
+                )
+            },
+        )?
+    };
+
+    let registry = registry.clone();
+    let config = config.clone();
+    thread::Builder::new()
+        .name("http-acceptor".to_owned())
+        .spawn(move || accept_clients(listeners, pool, &config, &registry, &client_error_callback))
+}
+
+fn accept_clients(
+    listeners: Vec<Listener>,
+    pool: WorkerPool<(Socket, u64)>,
+    config: &ServerConfig,
+    registry: &Registry,
+    client_error_callback: &ErrorCallback,
+) {
+    #[cfg(unix)]
+    let waiter = match waiter::Waiter::new(registry) {
+        Ok(waiter) => waiter,
+        Err(error) => {
+            report_error(
+                client_error_callback,
+                HandleClientError::AcceptClientError(error),
+                &ErrorContext::default(),
+            );
+            return;
+        }
+    };
+
+    while !registry.is_shutting_down() {
+        let mut accepted = false;
+
+        for listener in &listeners {
+            // Accept client
+            let socket = match listener.accept() {
+                Ok(socket) => socket,
+                Err(error) => {
+                    if error.kind() != ErrorKind::WouldBlock {
+                        report_error(
+                            client_error_callback,
+                            HandleClientError::AcceptClientError(error),
+                            &ErrorContext::default(),
+                        );
+
+                        // Errors such as running out of file descriptors leave the client
+                        // waiting, so back off rather than spin on it
+                        thread::sleep(ACCEPT_POLL_INTERVAL);
+                    }
+                    continue;
+                }
+            };
+
+            accepted = true;
+
+            // Connections are tracked from here, so those waiting for a worker count too
+            let registered = socket
+                .set_nonblocking(false)
+                .and_then(|()| socket.try_clone())
+                .map(|copy| registry.register(copy, config.max_connections()));
+
+            let id = match registered {
+                Ok(Some(id)) => id,
+                Ok(None) => {
+                    registry.record_shed(Shed::ConnectionLimit);
+                    shed_client(socket, config);
+                    continue;
+                }
+                Err(error) => {
+                    report_error(
+                        client_error_callback,
+                        HandleClientError::AcceptClientError(error),
+                        &ErrorContext::without_id(socket.peer_addr(), socket.local_addr()),
+                    );
+                    continue;
+                }
+            };
+
+            // Hand the client to a worker
+            let result = match config.overflow_policy() {
+                OverflowPolicy::Block => pool.execute((socket, id)),
+                OverflowPolicy::Reject => pool.try_execute((socket, id)),
+            };
+
+            if let Err((socket, id)) = result {
+                registry.unregister(id);
+                registry.record_shed(Shed::QueueLimit);
+                shed_client(socket, config);
+            }
+        }
+
+        if !accepted {
+            // Block until a client connects or the server shuts down
+            #[cfg(unix)]
+            if let Err(error) = waiter.wait(&listeners) {
+                report_error(
+                    client_error_callback,
+                    HandleClientError::AcceptClientError(error),
+                    &ErrorContext::default(),
+                );
+                return;
+            }
+
+            #[cfg(not(unix))]
+            thread::sleep(ACCEPT_POLL_INTERVAL);
+        }
+    }
+}
+
 impl std::error::Error for HandleClientError {}
 
 impl std::fmt::Display for HandleClientError {
@@ -112,6 +848,8 @@ impl std::fmt::Display for HandleClientError {
                     format!("Failed to read request - {}", error),
                 HandleClientError::WriteResponseError(error) =>
                     format!("Unable to write response ({})", error),
+                HandleClientError::HandlerPanic(message) =>
+                    format!("Handler panicked ({})", message),
             }
         )
     }
@@ -0,0 +1,83 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+pub struct WorkerPool<T: Send + 'static> {
+    sender: Option<SyncSender<T>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    pub fn new<F>(worker_count: usize, queue_depth: usize, handler: F) -> std::io::Result<Self>
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(queue_depth);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+
+        let mut workers = Vec::with_capacity(worker_count);
+        for i in 0..worker_count {
+            let receiver = receiver.clone();
+            let handler = handler.clone();
+            workers.push(
+                thread::Builder::new()
+                    .name(format!("http-worker-{}", i))
+                    .spawn(move || worker_loop(&receiver, &*handler))?,
+            );
+        }
+
+        Ok(WorkerPool {
+            sender: Some(sender),
+            workers,
+        })
+    }
+
+    // Queues a task, blocking while the queue is full
+    pub fn execute(&self, task: T) -> Result<(), T> {
+        match &self.sender {
+            Some(sender) => sender.send(task).map_err(|error| error.0),
+            None => Err(task),
+        }
+    }
+
+    // Queues a task, handing it back if the queue is full
+    pub fn try_execute(&self, task: T) -> Result<(), T> {
+        match &self.sender {
+            Some(sender) => match sender.try_send(task) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Full(task)) | Err(TrySendError::Disconnected(task)) => Err(task),
+            },
+            None => Err(task),
+        }
+    }
+}
+
+impl<T: Send + 'static> Drop for WorkerPool<T> {
+    fn drop(&mut self) {
+        // Closing the channel lets each worker finish the queue and exit
+        self.sender.take();
+
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
+
+fn worker_loop<T, F: Fn(T)>(receiver: &Mutex<Receiver<T>>, handler: &F) {
+    loop {
+        let task = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+
+        match task {
+            Ok(task) => handler(task),
+            Err(_) => return,
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use super::Server;
+use crate::{request, Request, Response, Status};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+type KeyFn = Box<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+// Wraps a Server, answering 429 to clients that send more than limit requests per period. Each
+// client has a token bucket holding up to limit requests, refilled evenly over the period.
+pub struct RateLimiter<S: Server> {
+    server: S,
+    limit: u32,
+    period: Duration,
+    key: KeyFn,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    entries: HashMap<String, Bucket>,
+    last_prune: Instant,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+enum Decision {
+    Allowed { remaining: f64 },
+    Limited { retry_after: f64 },
+}
+
+impl<S: Server> RateLimiter<S> {
+    // Clients are told apart by peer IP address, requests without one aren't limited
+    pub fn new(server: S, limit: u32, period: Duration) -> Self {
+        RateLimiter {
+            server,
+            limit: limit.max(1),
+            period: period.max(Duration::from_millis(1)),
+            key: Box::new(|request| request.peer_addr().map(|addr| addr.ip().to_string())),
+            buckets: Mutex::new(Buckets {
+                entries: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+        }
+    }
+
+    // Tells clients apart by key instead, such as an API key header. Requests it returns None for
+    // aren't limited.
+    pub fn with_key<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key = Box::new(key);
+        self
+    }
+
+    // Tokens added per second
+    fn rate(&self) -> f64 {
+        f64::from(self.limit) / self.period.as_secs_f64()
+    }
+
+    fn take(&self, key: String) -> Decision {
+        let now = Instant::now();
+        let capacity = f64::from(self.limit);
+        let rate = self.rate();
+
+        let mut buckets = self.lock();
+
+        // Drop buckets that have filled up again, they are the same as new ones
+        if now.duration_since(buckets.last_prune) >= self.period {
+            buckets.entries.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate < capacity
+            });
+            buckets.last_prune = now;
+        }
+
+        let bucket = buckets.entries.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Decision::Allowed {
+                remaining: bucket.tokens,
+            }
+        } else {
+            Decision::Limited {
+                retry_after: (1.0 - bucket.tokens) / rate,
+            }
+        }
+    }
+
+    // Adds the RateLimit-Limit, RateLimit-Remaining and RateLimit-Reset fields, the reset being
+    // the seconds until the bucket is full again
+    fn insert_headers(&self, response: &mut Response, remaining: f64) {
+        let reset = (f64::from(self.limit) - remaining) / self.rate();
+
+        let header = response.header_mut();
+        header.insert_header("RateLimit-Limit".to_owned(), format!("{}", self.limit));
+        header.insert_header(
+            "RateLimit-Remaining".to_owned(),
+            format!("{}", remaining.floor() as u64),
+        );
+        header.insert_header("RateLimit-Reset".to_owned(), format!("{}", seconds(reset)));
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Buckets> {
+        match self.buckets.lock() {
+            Ok(buckets) => buckets,
+            Err(error) => error.into_inner(),
+        }
+    }
+}
+
+impl<S: Server> Server for RateLimiter<S> {
+    fn handle_request(&self, request: Request) -> Response {
+        let key = match (self.key)(&request) {
+            Some(key) => key,
+            None => return self.server.handle_request(request),
+        };
+
+        match self.take(key) {
+            Decision::Allowed { remaining } => {
+                let mut response = self.server.handle_request(request);
+                self.insert_headers(&mut response, remaining);
+                response
+            }
+            Decision::Limited { retry_after } => {
+                let mut response = Response::new_status(Status::TooManyRequests, None);
+                self.insert_headers(&mut response, 0.0);
+                response.header_mut().insert_header(
+                    "Retry-After".to_owned(),
+                    format!("{}", seconds(retry_after)),
+                );
+                response
+            }
+        }
+    }
+
+    fn check_continue(&self, header: &request::Header) -> Option<Response> {
+        self.server.check_continue(header)
+    }
+}
+
+// Rounds up, so clients waiting that long will find room
+fn seconds(seconds: f64) -> u64 {
+    seconds.ceil() as u64
+}
@@ -1,70 +1,213 @@
-use crate::{request, Request, RequestParseError};
-use std::{io::Read, net::TcpStream, num::ParseIntError};
+use super::{
+    chunked,
+    reader::{start_deadline, ConnectionReader},
+    stream::Stream,
+    ServerConfig,
+};
+use crate::{request, Request, RequestParseError, Status};
+use std::{collections::HashMap, io::ErrorKind};
 
 #[derive(Debug)]
 pub enum ReadError {
     ReadError(std::io::Error),
     InvalidUTF8(std::string::FromUtf8Error),
     RequestParseError(RequestParseError),
-    InvalidContentLength(ParseIntError),
+    InvalidContentLength(String),
+    Timeout,
+    RequestLineTooLong,
+    HeaderTooLarge,
+    TooManyHeaders,
+    BodyTooLarge,
+    IncompleteBody,
+    InvalidTransferEncoding(String),
+    UnsupportedTransferEncoding(String),
+    InvalidChunkSize(String),
+    ChunkLineTooLong,
+    InvalidChunkEnding,
+    IncompleteChunkedBody,
 }
 
-pub fn read_request(stream: &mut TcpStream) -> Result<Option<Request>, ReadError> {
+pub enum BodyLength {
+    Empty,
+    Fixed(usize),
+    Chunked,
+}
+
+pub fn read_header<T: Stream>(
+    stream: &mut T,
+    reader: &mut ConnectionReader,
+    config: &ServerConfig,
+) -> Result<Option<(request::Header, BodyLength)>, ReadError> {
     // Read until "\r\n\r\n"
-    let mut buffer = Vec::with_capacity(128);
+    let deadline = start_deadline(stream, config.header_read_timeout())?;
+    let mut scanned = 0;
+    let header_length = loop {
+        let buffered = reader.buffered();
+        if let Some(position) = find(&buffered[scanned..], b"\r\n\r\n") {
+            break scanned + position + 4;
+        }
 
-    let mut char_buffer = [0];
-    loop {
-        let bytes_read = stream.read(&mut char_buffer)?;
-        if bytes_read == 0 {
+        // Only the last three bytes can start a match once more data arrives
+        scanned = buffered.len().saturating_sub(3);
+
+        check_header_limits(buffered, config)?;
+
+        if reader.fill(stream, deadline)? == 0 {
             return Ok(None);
         }
+    };
 
-        buffer.push(char_buffer[0]);
+    let header = parse_header_bytes(&reader.buffered()[..header_length], config)?;
+    reader.consume(header_length);
 
-        if buffer.len() >= 4 {
-            let buffer_len = buffer.len();
-            if *buffer.get(buffer_len - 1).unwrap() == b'\n'
-                && *buffer.get(buffer_len - 2).unwrap() == b'\r'
-                && *buffer.get(buffer_len - 3).unwrap() == b'\n'
-                && *buffer.get(buffer_len - 4).unwrap() == b'\r'
-            {
-                break;
-            }
+    Ok(Some(header))
+}
+
+// Parses a header from the start of buffer, returning None until all of it has arrived
+pub fn parse_header(
+    buffer: &[u8],
+    config: &ServerConfig,
+) -> Result<Option<(request::Header, BodyLength, usize)>, ReadError> {
+    match find(buffer, b"\r\n\r\n") {
+        Some(position) => {
+            let header_length = position + 4;
+            let (header, body_length) = parse_header_bytes(&buffer[..header_length], config)?;
+            Ok(Some((header, body_length, header_length)))
+        }
+        None => {
+            check_header_limits(buffer, config)?;
+            Ok(None)
         }
     }
+}
 
-    let header_str = String::from_utf8(buffer)?;
+fn parse_header_bytes(
+    header_bytes: &[u8],
+    config: &ServerConfig,
+) -> Result<(request::Header, BodyLength), ReadError> {
+    // Leave out the final empty line when checking limits
+    check_header_limits(&header_bytes[..header_bytes.len() - 2], config)?;
 
     // Parse header
-    let header = request::Header::parse(header_str)?;
+    let header = request::Header::parse(String::from_utf8(header_bytes.to_vec())?)?;
+
+    // Transfer-Encoding overrides Content-Length
+    if let Some(transfer_encoding) = header.get_header("Transfer-Encoding") {
+        chunked::check_transfer_encoding(transfer_encoding)?;
+        return Ok((header, BodyLength::Chunked));
+    }
 
     // Check if there is a body
     let body_length = match header.get_header("Content-Length") {
-        None => return Ok(Some(Request::new(header, String::new()))),
-        Some(value) => value.parse()?,
+        None => return Ok((header, BodyLength::Empty)),
+        Some(value) => parse_content_length(value)?,
     };
 
     if body_length == 0 {
-        return Ok(Some(Request::new(header, String::new())));
+        return Ok((header, BodyLength::Empty));
     }
 
-    // Read body
-    let mut buffer = Vec::with_capacity(body_length);
-    loop {
-        let bytes_read = stream.read(&mut char_buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
+    if body_length > config.max_body_size() {
+        return Err(ReadError::BodyTooLarge);
+    }
 
-        buffer.push(char_buffer[0]);
+    Ok((header, BodyLength::Fixed(body_length)))
+}
 
-        if buffer.len() == body_length {
-            break;
-        }
+// Only digits are accepted, and a field repeated with different values is rejected since the
+// request could be framed either way
+fn parse_content_length(value: &str) -> Result<usize, ReadError> {
+    let invalid = || ReadError::InvalidContentLength(value.to_owned());
+
+    let mut lengths = value.split(',').map(str::trim);
+    let length = lengths.next().unwrap_or("");
+    if length.is_empty()
+        || !length.bytes().all(|byte| byte.is_ascii_digit())
+        || lengths.any(|other| other != length)
+    {
+        return Err(invalid());
     }
 
-    Ok(Some(Request::new(header, String::from_utf8(buffer)?)))
+    length.parse().map_err(|_| invalid())
+}
+
+pub fn read_body<T: Stream>(
+    stream: &mut T,
+    reader: &mut ConnectionReader,
+    config: &ServerConfig,
+    header: request::Header,
+    body_length: BodyLength,
+) -> Result<Request, ReadError> {
+    let deadline = start_deadline(stream, config.body_read_timeout())?;
+
+    let (body, trailers) = match body_length {
+        BodyLength::Empty => (Vec::new(), HashMap::new()),
+        BodyLength::Fixed(body_length) => (
+            reader.read_body(stream, body_length, deadline)?,
+            HashMap::new(),
+        ),
+        BodyLength::Chunked => chunked::read_chunked_body(stream, reader, config, deadline)?,
+    };
+
+    build_request(header, body, trailers)
+}
+
+pub fn build_request(
+    header: request::Header,
+    body: Vec<u8>,
+    trailers: HashMap<String, String>,
+) -> Result<Request, ReadError> {
+    Ok(Request::with_trailers(
+        header,
+        String::from_utf8(body)?,
+        trailers,
+    ))
+}
+
+fn check_header_limits(header: &[u8], config: &ServerConfig) -> Result<(), ReadError> {
+    let request_line_length = find(header, b"\r\n").unwrap_or(header.len());
+    if request_line_length > config.max_request_line_length() {
+        return Err(ReadError::RequestLineTooLong);
+    }
+
+    let lines = match header.get(request_line_length + 2..) {
+        Some(lines) => lines,
+        None => return Ok(()),
+    };
+
+    if lines.len() > config.max_header_size() {
+        return Err(ReadError::HeaderTooLarge);
+    }
+
+    if lines.windows(2).filter(|window| window == b"\r\n").count() > config.max_header_count() {
+        return Err(ReadError::TooManyHeaders);
+    }
+
+    Ok(())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+impl ReadError {
+    pub fn status(&self) -> Status {
+        match self {
+            ReadError::Timeout => Status::RequestTimeout,
+            ReadError::RequestParseError(RequestParseError::UnsupportedHTTPVersion) => {
+                Status::HTTPVersionNotSupported
+            }
+            ReadError::RequestLineTooLong => Status::RequestURITooLong,
+            ReadError::HeaderTooLarge | ReadError::TooManyHeaders => {
+                Status::RequestHeaderFieldsTooLarge
+            }
+            ReadError::BodyTooLarge => Status::RequestEntityTooLarge,
+            ReadError::UnsupportedTransferEncoding(_) => Status::NotImplemented,
+            _ => Status::BadRequest,
+        }
+    }
 }
 
 impl std::error::Error for ReadError {}
@@ -81,6 +224,20 @@ impl std::fmt::Display for ReadError {
                     format!("Unable to parse request ({})", error),
                 ReadError::InvalidContentLength(error) =>
                     format!("Invalid Content-Length value ({})", error),
+                ReadError::Timeout => "Timed out reading request".to_owned(),
+                ReadError::RequestLineTooLong => "Request line too long".to_owned(),
+                ReadError::HeaderTooLarge => "Request header too large".to_owned(),
+                ReadError::TooManyHeaders => "Too many request headers".to_owned(),
+                ReadError::BodyTooLarge => "Request body too large".to_owned(),
+                ReadError::IncompleteBody => "Request body ended early".to_owned(),
+                ReadError::InvalidTransferEncoding(value) =>
+                    format!("Invalid Transfer-Encoding value ({})", value),
+                ReadError::UnsupportedTransferEncoding(coding) =>
+                    format!("Unsupported transfer coding ({})", coding),
+                ReadError::InvalidChunkSize(line) => format!("Invalid chunk size ({})", line),
+                ReadError::ChunkLineTooLong => "Chunk size line too long".to_owned(),
+                ReadError::InvalidChunkEnding => "Chunk data not followed by CRLF".to_owned(),
+                ReadError::IncompleteChunkedBody => "Chunked body ended early".to_owned(),
             }
         )
     }
@@ -88,7 +245,10 @@ impl std::fmt::Display for ReadError {
 
 impl From<std::io::Error> for ReadError {
     fn from(error: std::io::Error) -> Self {
-        ReadError::ReadError(error)
+        match error.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => ReadError::Timeout,
+            _ => ReadError::ReadError(error),
+        }
     }
 }
 
@@ -103,9 +263,3 @@ impl From<RequestParseError> for ReadError {
         ReadError::RequestParseError(error)
     }
 }
-
-impl From<ParseIntError> for ReadError {
-    fn from(error: ParseIntError) -> Self {
-        ReadError::InvalidContentLength(error)
-    }
-}
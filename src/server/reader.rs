@@ -0,0 +1,126 @@
+use super::{stream::Stream, ReadError};
+use std::time::{Duration, Instant};
+
+const INITIAL_CAPACITY: usize = 8 * 1024;
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+const BODY_PREALLOCATE_LIMIT: usize = 64 * 1024;
+
+// Buffers reads from a connection, keeping any bytes past the current request for the next one
+pub struct ConnectionReader {
+    buffer: Vec<u8>,
+    start: usize,
+    end: usize,
+    total_read: u64,
+}
+
+impl ConnectionReader {
+    pub fn new() -> Self {
+        ConnectionReader {
+            buffer: vec![0; INITIAL_CAPACITY],
+            start: 0,
+            end: 0,
+            total_read: 0,
+        }
+    }
+
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer[self.start..self.end]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    // Counts every byte read from the stream, including those not consumed yet
+    pub fn total_read(&self) -> u64 {
+        self.total_read
+    }
+
+    pub fn consume(&mut self, length: usize) {
+        self.start = (self.start + length).min(self.end);
+    }
+
+    // Reads the next chunk from the stream into the buffer, returning 0 at end of stream
+    pub fn fill<T: Stream>(
+        &mut self,
+        stream: &mut T,
+        deadline: Option<Instant>,
+    ) -> Result<usize, ReadError> {
+        if self.is_empty() {
+            self.start = 0;
+            self.end = 0;
+        } else if self.start > 0 && self.buffer.len() - self.end < READ_CHUNK_SIZE {
+            self.buffer.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+
+        if self.buffer.len() - self.end < READ_CHUNK_SIZE {
+            self.buffer.resize(self.end + READ_CHUNK_SIZE, 0);
+        }
+
+        let bytes_read = read_before(stream, &mut self.buffer[self.end..], deadline)?;
+        self.end += bytes_read;
+        self.total_read += bytes_read as u64;
+        Ok(bytes_read)
+    }
+
+    // Reads length bytes, failing if the stream ends before they arrive
+    pub fn read_body<T: Stream>(
+        &mut self,
+        stream: &mut T,
+        length: usize,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<u8>, ReadError> {
+        let mut body = Vec::with_capacity(length.min(BODY_PREALLOCATE_LIMIT));
+
+        // Take what is already buffered
+        let buffered = (self.end - self.start).min(length);
+        body.extend_from_slice(&self.buffer[self.start..self.start + buffered]);
+        self.consume(buffered);
+
+        // Read the rest straight into the body
+        while body.len() < length {
+            let body_length = body.len();
+            body.resize(body_length + (length - body_length).min(READ_CHUNK_SIZE), 0);
+
+            let bytes_read = read_before(stream, &mut body[body_length..], deadline)?;
+            body.truncate(body_length + bytes_read);
+            self.total_read += bytes_read as u64;
+
+            if bytes_read == 0 {
+                return Err(ReadError::IncompleteBody);
+            }
+        }
+
+        Ok(body)
+    }
+}
+
+pub fn start_deadline<T: Stream>(
+    stream: &T,
+    timeout: Option<Duration>,
+) -> Result<Option<Instant>, ReadError> {
+    if timeout.is_none() {
+        stream.set_read_timeout(None)?;
+    }
+
+    Ok(timeout.map(|timeout| Instant::now() + timeout))
+}
+
+fn read_before<T: Stream>(
+    stream: &mut T,
+    buffer: &mut [u8],
+    deadline: Option<Instant>,
+) -> Result<usize, ReadError> {
+    if let Some(deadline) = deadline {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(ReadError::Timeout);
+        }
+
+        stream.set_read_timeout(Some(deadline - now))?;
+    }
+
+    Ok(stream.read(buffer)?)
+}
@@ -0,0 +1,198 @@
+#[cfg(unix)]
+use super::unix::SocketFile;
+use super::{socket::Socket, stats::LoadStats};
+use std::{
+    collections::HashMap,
+    net::Shutdown,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+pub struct Registry {
+    shutdown: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+    connections: Mutex<Connections>,
+    empty: Condvar,
+    shed_at_connection_limit: AtomicU64,
+    shed_at_queue_limit: AtomicU64,
+    // Shared by the handle and the serving threads, so the files go once both are done with them
+    #[cfg(unix)]
+    socket_files: Mutex<Vec<SocketFile>>,
+}
+
+// Why a client was turned away
+pub enum Shed {
+    ConnectionLimit,
+    QueueLimit,
+}
+
+// Wakes a thread blocked waiting for clients so it sees the shutdown
+type Waker = Box<dyn Fn() + Send + Sync>;
+
+struct Connections {
+    next_id: u64,
+    entries: HashMap<u64, Entry>,
+}
+
+struct Entry {
+    stream: Socket,
+    idle: bool,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry {
+            shutdown: AtomicBool::new(false),
+            waker: Mutex::new(None),
+            connections: Mutex::new(Connections {
+                next_id: 0,
+                entries: HashMap::new(),
+            }),
+            empty: Condvar::new(),
+            shed_at_connection_limit: AtomicU64::new(0),
+            shed_at_queue_limit: AtomicU64::new(0),
+            #[cfg(unix)]
+            socket_files: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    pub fn set_waker<F: Fn() + Send + Sync + 'static>(&self, waker: F) {
+        match self.waker.lock() {
+            Ok(mut slot) => *slot = Some(Box::new(waker)),
+            Err(error) => *error.into_inner() = Some(Box::new(waker)),
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn set_socket_files(&self, socket_files: Vec<SocketFile>) {
+        *self.socket_files() = socket_files;
+    }
+
+    // Removes the socket files now rather than when the registry is dropped
+    #[cfg(unix)]
+    pub fn remove_socket_files(&self) {
+        self.socket_files().clear();
+    }
+
+    #[cfg(unix)]
+    pub fn disown_socket_files(&self) {
+        for file in self.socket_files().drain(..) {
+            file.disown();
+        }
+    }
+
+    #[cfg(unix)]
+    fn socket_files(&self) -> MutexGuard<'_, Vec<SocketFile>> {
+        match self.socket_files.lock() {
+            Ok(socket_files) => socket_files,
+            Err(error) => error.into_inner(),
+        }
+    }
+
+    // Tracks a connection so it can be closed during shutdown, stream is a copy of its socket.
+    // Returns None if max_connections are already open.
+    pub fn register(&self, stream: Socket, max_connections: usize) -> Option<u64> {
+        let mut connections = self.lock();
+        if connections.entries.len() >= max_connections {
+            return None;
+        }
+
+        let id = connections.next_id;
+        connections.next_id += 1;
+        connections.entries.insert(id, Entry { stream, idle: true });
+        Some(id)
+    }
+
+    pub fn unregister(&self, id: u64) {
+        let mut connections = self.lock();
+        connections.entries.remove(&id);
+        if connections.entries.is_empty() {
+            self.empty.notify_all();
+        }
+    }
+
+    // Returns false if the server is shutting down and the connection should close
+    pub fn set_idle(&self, id: u64, idle: bool) -> bool {
+        let mut connections = self.lock();
+        if let Some(entry) = connections.entries.get_mut(&id) {
+            entry.idle = idle;
+        }
+
+        !self.is_shutting_down()
+    }
+
+    // Stops new requests and closes every connection waiting for one
+    pub fn begin_shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        let waker = match self.waker.lock() {
+            Ok(waker) => waker,
+            Err(error) => error.into_inner(),
+        };
+        if let Some(wake) = &*waker {
+            wake();
+        }
+        drop(waker);
+
+        let connections = self.lock();
+        for entry in connections.entries.values() {
+            if entry.idle {
+                entry.stream.shutdown(Shutdown::Both).ok();
+            }
+        }
+    }
+
+    // Waits for in-flight requests to finish, closing any left after the deadline. Returns false
+    // if connections were still open then.
+    pub fn drain(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+
+        let mut connections = self.lock();
+        while !connections.entries.is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                for entry in connections.entries.values() {
+                    entry.stream.shutdown(Shutdown::Both).ok();
+                }
+                return false;
+            }
+
+            connections = match self.empty.wait_timeout(connections, deadline - now) {
+                Ok((connections, _)) => connections,
+                Err(error) => error.into_inner().0,
+            };
+        }
+
+        true
+    }
+
+    pub fn record_shed(&self, shed: Shed) {
+        let counter = match shed {
+            Shed::ConnectionLimit => &self.shed_at_connection_limit,
+            Shed::QueueLimit => &self.shed_at_queue_limit,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> LoadStats {
+        LoadStats::new(
+            self.lock().entries.len(),
+            self.shed_at_connection_limit.load(Ordering::Relaxed),
+            self.shed_at_queue_limit.load(Ordering::Relaxed),
+        )
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connections> {
+        match self.connections.lock() {
+            Ok(connections) => connections,
+            Err(error) => error.into_inner(),
+        }
+    }
+}
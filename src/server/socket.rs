@@ -0,0 +1,172 @@
+#[cfg(unix)]
+use std::os::unix::{
+    io::{AsRawFd, FromRawFd, RawFd},
+    net::{UnixListener, UnixStream},
+};
+use std::{
+    io::Write,
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+};
+
+// A socket clients are accepted from
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+// An accepted client connection
+pub enum Socket {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+// Binds addr like TcpListener::bind, except that an IPv6 socket also accepts IPv4 clients
+// whatever the platform default, so "[::]" listens on both
+pub fn bind_tcp(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    match addr {
+        #[cfg(unix)]
+        SocketAddr::V6(addr) => bind_dual_stack(addr),
+        _ => TcpListener::bind(addr),
+    }
+}
+
+#[cfg(unix)]
+fn bind_dual_stack(addr: std::net::SocketAddrV6) -> std::io::Result<TcpListener> {
+    let fd = unsafe { libc::socket(libc::AF_INET6, libc::SOCK_STREAM, 0) };
+    if fd == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // Owning the socket closes it on the error paths below
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    let check = |result: libc::c_int| match result {
+        -1 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    };
+
+    check(unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) })?;
+
+    // The options TcpListener::bind would set, then dual-stack on top
+    let enable: libc::c_int = 1;
+    let disable: libc::c_int = 0;
+    for &(level, option, value) in &[
+        (libc::SOL_SOCKET, libc::SO_REUSEADDR, &enable),
+        (libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, &disable),
+    ] {
+        check(unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                option,
+                value as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        })?;
+    }
+
+    let mut address: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+    address.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+    address.sin6_port = addr.port().to_be();
+    address.sin6_flowinfo = addr.flowinfo();
+    address.sin6_addr.s6_addr = addr.ip().octets();
+    address.sin6_scope_id = addr.scope_id();
+    check(unsafe {
+        libc::bind(
+            fd,
+            &address as *const libc::sockaddr_in6 as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+        )
+    })?;
+    check(unsafe { libc::listen(fd, 128) })?;
+
+    Ok(listener)
+}
+
+impl Listener {
+    pub fn accept(&self) -> std::io::Result<Socket> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| Socket::Tcp(stream)),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.accept().map(|(stream, _)| Socket::Unix(stream)),
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        match self {
+            Listener::Tcp(listener) => listener.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.set_nonblocking(nonblocking),
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Listener::Tcp(listener) => listener.as_raw_fd(),
+            Listener::Unix(listener) => listener.as_raw_fd(),
+        }
+    }
+}
+
+impl Socket {
+    pub fn try_clone(&self) -> std::io::Result<Self> {
+        match self {
+            Socket::Tcp(stream) => stream.try_clone().map(Socket::Tcp),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.try_clone().map(Socket::Unix),
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+
+    // Unix sockets are usually unnamed on the client side, so only TCP has addresses
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Socket::Tcp(stream) => stream.peer_addr().ok(),
+            #[cfg(unix)]
+            Socket::Unix(_) => None,
+        }
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Socket::Tcp(stream) => stream.local_addr().ok(),
+            #[cfg(unix)]
+            Socket::Unix(_) => None,
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.shutdown(how),
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Socket::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.flush(),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+// Connection counts of a running server, read at one moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadStats {
+    open_connections: usize,
+    shed_at_connection_limit: u64,
+    shed_at_queue_limit: u64,
+}
+
+impl LoadStats {
+    pub(super) fn new(
+        open_connections: usize,
+        shed_at_connection_limit: u64,
+        shed_at_queue_limit: u64,
+    ) -> Self {
+        LoadStats {
+            open_connections,
+            shed_at_connection_limit,
+            shed_at_queue_limit,
+        }
+    }
+
+    // Includes connections waiting for a worker
+    pub fn open_connections(&self) -> usize {
+        self.open_connections
+    }
+
+    // Clients turned away because max_connections were open
+    pub fn shed_at_connection_limit(&self) -> u64 {
+        self.shed_at_connection_limit
+    }
+
+    // Clients, or requests with the epoll backend, turned away because the worker queue was full
+    pub fn shed_at_queue_limit(&self) -> u64 {
+        self.shed_at_queue_limit
+    }
+
+    pub fn shed_total(&self) -> u64 {
+        self.shed_at_connection_limit + self.shed_at_queue_limit
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{PeerCredentials, TlsInfo};
+use std::{
+    io::{Read, Write},
+    net::{Shutdown, TcpStream},
+    time::Duration,
+};
+
+// A connection requests are read from and responses written to
+pub trait Stream: Read + Write {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+
+    // Tells the client no more data will be sent while still reading from it
+    fn shutdown_write(&mut self) -> std::io::Result<()>;
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        None
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        None
+    }
+}
+
+impl Stream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn shutdown_write(&mut self) -> std::io::Result<()> {
+        self.shutdown(Shutdown::Write)
+    }
+}
+
+// Adapts any reader and writer, deadlines are only checked between reads since there is no socket
+// to set timeouts on
+pub struct Transport<T>(pub T);
+
+impl<T: Read> Read for Transport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<T: Write> Write for Transport<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<T: Read + Write> Stream for Transport<T> {
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn shutdown_write(&mut self) -> std::io::Result<()> {
+        self.flush()
+    }
+}
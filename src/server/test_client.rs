@@ -0,0 +1,270 @@
+use super::{chunked::ChunkedDecoder, serve_connection, Server, ServerConfig};
+use crate::{Method, Status};
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read, Write},
+};
+
+// Sends requests to a Server through the same parsing and response writing as a real connection,
+// without binding a port
+pub struct TestClient<'a, S: Server> {
+    server: &'a S,
+    config: ServerConfig,
+}
+
+pub struct TestRequest<'a, S: Server> {
+    client: &'a TestClient<'a, S>,
+    method: Method,
+    uri: String,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+}
+
+pub struct TestResponse {
+    status_code: usize,
+    reason_phrase: String,
+    headers: HashMap<String, String>,
+    body: String,
+    trailers: HashMap<String, String>,
+}
+
+// Feeds the request in and collects everything written back
+struct Pipe<'a> {
+    input: Cursor<&'a [u8]>,
+    output: Vec<u8>,
+}
+
+impl<'a, S: Server> TestClient<'a, S> {
+    pub fn new(server: &'a S) -> Self {
+        TestClient::with_config(server, ServerConfig::new())
+    }
+
+    pub fn with_config(server: &'a S, config: ServerConfig) -> Self {
+        TestClient { server, config }
+    }
+
+    pub fn request<U: Into<String>>(&self, method: Method, uri: U) -> TestRequest<'_, S> {
+        TestRequest {
+            client: self,
+            method,
+            uri: uri.into(),
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn get<U: Into<String>>(&self, uri: U) -> TestRequest<'_, S> {
+        self.request(Method::Get, uri)
+    }
+
+    pub fn post<U: Into<String>>(&self, uri: U) -> TestRequest<'_, S> {
+        self.request(Method::Post, uri)
+    }
+
+    pub fn put<U: Into<String>>(&self, uri: U) -> TestRequest<'_, S> {
+        self.request(Method::Put, uri)
+    }
+
+    pub fn delete<U: Into<String>>(&self, uri: U) -> TestRequest<'_, S> {
+        self.request(Method::Delete, uri)
+    }
+
+    // Sends bytes exactly as given, for requests the builder can't express such as malformed ones.
+    // Panics if the server doesn't answer.
+    pub fn send_raw(&self, request: &[u8]) -> TestResponse {
+        let mut pipe = Pipe {
+            input: Cursor::new(request),
+            output: Vec::new(),
+        };
+        let result = serve_connection(&mut pipe, self.server, &self.config);
+
+        match (TestResponse::parse(&pipe.output), result) {
+            (Ok(response), _) => response,
+            (Err(error), Err(client_error)) => panic!("{} ({})", error, client_error),
+            (Err(error), Ok(())) => panic!("{}", error),
+        }
+    }
+}
+
+impl<'a, S: Server> TestRequest<'a, S> {
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
+
+    // Sent with a Content-Length unless a framing header was set
+    pub fn body<B: Into<String>>(mut self, body: B) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub fn send(self) -> TestResponse {
+        let mut request = format!("{} {} HTTP/1.1\r\n", self.method, self.uri);
+        for (key, value) in &self.headers {
+            request.push_str(&format!("{}: {}\r\n", key, value));
+        }
+
+        if let Some(body) = &self.body {
+            let framed = self.headers.iter().any(|(key, _)| {
+                key.eq_ignore_ascii_case("Content-Length")
+                    || key.eq_ignore_ascii_case("Transfer-Encoding")
+            });
+            if !framed {
+                request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+            }
+        }
+
+        request.push_str("\r\n");
+        if let Some(body) = &self.body {
+            request.push_str(body);
+        }
+
+        self.client.send_raw(request.as_bytes())
+    }
+}
+
+impl TestResponse {
+    pub fn status_code(&self) -> usize {
+        self.status_code
+    }
+
+    pub fn reason_phrase(&self) -> &str {
+        &self.reason_phrase
+    }
+
+    pub fn header<S: AsRef<str>>(&self, key: S) -> Option<&str> {
+        self.headers
+            .get(&key.as_ref().to_ascii_lowercase())
+            .map(|s| s.as_str())
+    }
+
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    // Fields sent after a streamed body
+    pub fn trailer<S: AsRef<str>>(&self, key: S) -> Option<&str> {
+        self.trailers
+            .get(&key.as_ref().to_ascii_lowercase())
+            .map(|s| s.as_str())
+    }
+
+    pub fn assert_status(&self, status: Status) -> &Self {
+        assert_eq!(
+            self.status_code,
+            status.code(),
+            "Unexpected status {} {} with body {:?}",
+            self.status_code,
+            self.reason_phrase,
+            self.body
+        );
+        self
+    }
+
+    pub fn assert_header<S: AsRef<str>>(&self, key: S, value: &str) -> &Self {
+        assert_eq!(
+            self.header(&key),
+            Some(value),
+            "Unexpected {} header",
+            key.as_ref()
+        );
+        self
+    }
+
+    pub fn assert_body(&self, body: &str) -> &Self {
+        assert_eq!(self.body, body, "Unexpected body");
+        self
+    }
+
+    // Reads the first final response, skipping interim ones such as "100 Continue"
+    fn parse(mut output: &[u8]) -> Result<Self, String> {
+        loop {
+            let header_length = match output.windows(4).position(|window| window == b"\r\n\r\n") {
+                Some(position) => position,
+                None if output.is_empty() => return Err("No response".to_owned()),
+                None => return Err("Incomplete response header".to_owned()),
+            };
+            let header = String::from_utf8_lossy(&output[..header_length]).into_owned();
+            output = &output[header_length + 4..];
+
+            let mut lines = header.split("\r\n");
+            let mut status_line = lines.next().unwrap_or("").splitn(3, ' ');
+            status_line.next();
+            let status_code = match status_line.next().map(str::parse::<usize>) {
+                Some(Ok(status_code)) => status_code,
+                _ => return Err(format!("Invalid status line in {:?}", header)),
+            };
+            let reason_phrase = status_line.next().unwrap_or("").to_owned();
+
+            let mut headers = HashMap::new();
+            for line in lines {
+                if let Some((key, value)) = line.split_once(':') {
+                    headers.insert(key.trim().to_ascii_lowercase(), value.trim().to_owned());
+                }
+            }
+
+            if (100..200).contains(&status_code) {
+                continue;
+            }
+
+            let mut response = TestResponse {
+                status_code,
+                reason_phrase,
+                headers,
+                body: String::new(),
+                trailers: HashMap::new(),
+            };
+            response.read_body(output)?;
+            return Ok(response);
+        }
+    }
+
+    fn read_body(&mut self, output: &[u8]) -> Result<(), String> {
+        let body = if self.header("Transfer-Encoding").is_some() {
+            // Responses are trusted, so lift the limits meant for clients
+            let mut config = ServerConfig::new();
+            config
+                .set_max_body_size(usize::MAX)
+                .set_max_header_size(usize::MAX);
+
+            let mut decoder = ChunkedDecoder::new();
+            decoder
+                .decode(output, &config)
+                .map_err(|error| format!("Invalid chunked body ({})", error))?;
+            if !decoder.is_done() {
+                return Err("Incomplete chunked body".to_owned());
+            }
+
+            let (body, trailers) = decoder.into_parts();
+            self.trailers = trailers;
+            body
+        } else {
+            // Without a length the body runs until the connection closes
+            let length = match self.header("Content-Length").map(str::parse::<usize>) {
+                Some(Ok(length)) => length.min(output.len()),
+                Some(Err(_)) => return Err("Invalid Content-Length".to_owned()),
+                None => output.len(),
+            };
+            output[..length].to_vec()
+        };
+
+        self.body = String::from_utf8_lossy(&body).into_owned();
+        Ok(())
+    }
+}
+
+impl Read for Pipe<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Pipe<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
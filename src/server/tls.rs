@@ -0,0 +1,237 @@
+use super::{stream::Stream, ServerConfig};
+use crate::TlsInfo;
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{danger::ClientCertVerifier, ClientHello, ResolvesServerCert, WebPkiClientVerifier},
+    sign::CertifiedKey,
+    RootCertStore, ServerConnection, StreamOwned,
+};
+use std::{
+    collections::HashMap,
+    io::Write,
+    net::{Shutdown, TcpStream},
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    provider: Arc<CryptoProvider>,
+    certificates: Certificates,
+    client_verifier: Option<Arc<dyn ClientCertVerifier>>,
+    alpn_protocols: Vec<Vec<u8>>,
+}
+
+// Picks the certificate for the name a client asks for through SNI
+#[derive(Debug, Clone)]
+struct Certificates {
+    default: Arc<CertifiedKey>,
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    PEMError(String, rustls::pki_types::pem::Error),
+    NoCertificates(String),
+    CertificateError(rustls::Error),
+    ClientVerifierError(rustls::server::VerifierBuilderError),
+}
+
+impl TlsConfig {
+    pub fn from_pem_files<P: AsRef<Path>, K: AsRef<Path>>(
+        cert_chain: P,
+        private_key: K,
+    ) -> Result<Self, TlsError> {
+        let provider = Arc::new(ring::default_provider());
+        let default = load_certified_key(&provider, cert_chain.as_ref(), private_key.as_ref())?;
+
+        Ok(TlsConfig {
+            provider,
+            certificates: Certificates {
+                default,
+                by_name: HashMap::new(),
+            },
+            client_verifier: None,
+            alpn_protocols: Vec::new(),
+        })
+    }
+
+    // Serves a different certificate to clients asking for server_name, others get the default
+    pub fn add_sni_certificate<P: AsRef<Path>, K: AsRef<Path>>(
+        &mut self,
+        server_name: &str,
+        cert_chain: P,
+        private_key: K,
+    ) -> Result<&mut Self, TlsError> {
+        let certified_key =
+            load_certified_key(&self.provider, cert_chain.as_ref(), private_key.as_ref())?;
+
+        self.certificates
+            .by_name
+            .insert(server_name.to_ascii_lowercase(), certified_key);
+        Ok(self)
+    }
+
+    // Asks clients for a certificate signed by one of the authorities in ca_file, clients without
+    // one are turned away only if required
+    pub fn set_client_ca_file<P: AsRef<Path>>(
+        &mut self,
+        ca_file: P,
+        required: bool,
+    ) -> Result<&mut Self, TlsError> {
+        let mut roots = RootCertStore::empty();
+        for certificate in load_certificates(ca_file.as_ref())? {
+            roots.add(certificate).map_err(TlsError::CertificateError)?;
+        }
+
+        let mut builder =
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), self.provider.clone());
+        if !required {
+            builder = builder.allow_unauthenticated();
+        }
+
+        self.client_verifier = Some(builder.build().map_err(TlsError::ClientVerifierError)?);
+        Ok(self)
+    }
+
+    // Protocols offered through ALPN, most preferred first
+    pub fn set_alpn_protocols(&mut self, alpn_protocols: Vec<Vec<u8>>) -> &mut Self {
+        self.alpn_protocols = alpn_protocols;
+        self
+    }
+
+    pub(super) fn build(&self) -> Result<Arc<rustls::ServerConfig>, TlsError> {
+        let builder = rustls::ServerConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(TlsError::CertificateError)?;
+
+        let builder = match &self.client_verifier {
+            Some(client_verifier) => builder.with_client_cert_verifier(client_verifier.clone()),
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder.with_cert_resolver(Arc::new(self.certificates.clone()));
+        config.alpn_protocols = self.alpn_protocols.clone();
+        Ok(Arc::new(config))
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let certified_key = client_hello
+            .server_name()
+            .and_then(|server_name| self.by_name.get(&server_name.to_ascii_lowercase()));
+
+        Some(certified_key.unwrap_or(&self.default).clone())
+    }
+}
+
+// Completes the handshake within the header timeout
+pub fn accept(
+    mut stream: TcpStream,
+    tls: &Arc<rustls::ServerConfig>,
+    config: &ServerConfig,
+) -> std::io::Result<TlsStream> {
+    stream.set_write_timeout(config.write_timeout())?;
+
+    let mut connection = ServerConnection::new(tls.clone()).map_err(std::io::Error::other)?;
+
+    let deadline = config
+        .header_read_timeout()
+        .map(|timeout| Instant::now() + timeout);
+    while connection.is_handshaking() {
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(std::io::ErrorKind::TimedOut.into());
+            }
+
+            stream.set_read_timeout(Some(remaining))?;
+        }
+
+        connection.complete_io(&mut stream)?;
+    }
+
+    Ok(StreamOwned::new(connection, stream))
+}
+
+impl Stream for TlsStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.sock.set_write_timeout(timeout)
+    }
+
+    fn shutdown_write(&mut self) -> std::io::Result<()> {
+        self.conn.send_close_notify();
+        self.flush()?;
+        self.sock.shutdown(Shutdown::Write)
+    }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        let peer_certificates = match self.conn.peer_certificates() {
+            Some(certificates) => certificates
+                .iter()
+                .map(|certificate| certificate.to_vec())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Some(TlsInfo::new(
+            self.conn.server_name().map(str::to_owned),
+            self.conn.alpn_protocol().map(<[u8]>::to_vec),
+            peer_certificates,
+        ))
+    }
+}
+
+fn load_certified_key(
+    provider: &CryptoProvider,
+    cert_chain: &Path,
+    private_key: &Path,
+) -> Result<Arc<CertifiedKey>, TlsError> {
+    let cert_chain = load_certificates(cert_chain)?;
+    let private_key = PrivateKeyDer::from_pem_file(private_key)
+        .map_err(|error| TlsError::PEMError(private_key.display().to_string(), error))?;
+
+    CertifiedKey::from_der(cert_chain, private_key, provider)
+        .map(Arc::new)
+        .map_err(TlsError::CertificateError)
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certificates = CertificateDer::pem_file_iter(path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .map_err(|error| TlsError::PEMError(path.display().to_string(), error))?;
+
+    if certificates.is_empty() {
+        return Err(TlsError::NoCertificates(path.display().to_string()));
+    }
+
+    Ok(certificates)
+}
+
+impl std::error::Error for TlsError {}
+
+impl std::fmt::Display for TlsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TlsError::PEMError(path, error) =>
+                    format!("Unable to read PEM file {} ({})", path, error),
+                TlsError::NoCertificates(path) => format!("No certificates in {}", path),
+                TlsError::CertificateError(error) => format!("Invalid certificate ({})", error),
+                TlsError::ClientVerifierError(error) =>
+                    format!("Unable to verify client certificates ({})", error),
+            }
+        )
+    }
+}
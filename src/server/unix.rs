@@ -0,0 +1,207 @@
+use super::stream::Stream;
+use crate::PeerCredentials;
+use std::{
+    fs,
+    io::{ErrorKind, Read, Write},
+    net::Shutdown,
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+// Tells apart the staging directories of binds in the same process
+static STAGING_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// A socket file the server bound, removed again when this is dropped unless it was disowned
+pub struct SocketFile {
+    path: PathBuf,
+    // Device and inode, so a file another server bound at the same path since is left alone
+    identity: Option<(u64, u64)>,
+}
+
+// A Unix socket client, with the peer's credentials looked up once when it is accepted
+pub struct UnixConnection {
+    stream: UnixStream,
+    peer_credentials: Option<PeerCredentials>,
+}
+
+// Binds a socket file at path, replacing one left behind by a server that is no longer running
+pub fn bind(path: &Path, mode: Option<u32>) -> std::io::Result<(UnixListener, SocketFile)> {
+    remove_stale_socket(path)?;
+
+    let listener = match mode {
+        Some(mode) => bind_with_mode(path, mode)?,
+        None => UnixListener::bind(path)?,
+    };
+    let metadata = fs::symlink_metadata(path)?;
+    let file = SocketFile {
+        path: path.to_owned(),
+        identity: Some((metadata.dev(), metadata.ino())),
+    };
+
+    Ok((listener, file))
+}
+
+// Binds inside a directory only the owner can enter and links the socket into place once it has
+// its mode, so no client can connect while the umask still applies
+fn bind_with_mode(path: &Path, mode: u32) -> std::io::Result<UnixListener> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let count = STAGING_COUNT.fetch_add(1, Ordering::Relaxed);
+    let staging = parent.join(format!(".bind-{}-{}", process::id(), count));
+
+    // One with the same name can only be left from a crashed process that had the same id
+    remove_staging(&staging);
+    fs::DirBuilder::new().mode(0o700).create(&staging)?;
+
+    let staged = staging.join("socket");
+    let result = UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, fs::Permissions::from_mode(mode))?;
+        // Unlike a rename this fails rather than replacing a file bound at path meanwhile
+        fs::hard_link(&staged, path)?;
+        Ok(listener)
+    });
+
+    remove_staging(&staging);
+    result
+}
+
+fn remove_staging(staging: &Path) {
+    fs::remove_file(staging.join("socket")).ok();
+    fs::remove_dir(staging).ok();
+}
+
+// Only removes sockets nothing is listening on, never other files
+fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(std::io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+
+    match UnixStream::connect(path) {
+        Ok(_) => Err(std::io::Error::new(
+            ErrorKind::AddrInUse,
+            format!("Another server is listening on {}", path.display()),
+        )),
+        Err(error) if error.kind() == ErrorKind::ConnectionRefused => fs::remove_file(path),
+        Err(error) => Err(error),
+    }
+}
+
+impl SocketFile {
+    // Leaves the file for another process that took over the socket
+    pub fn disown(mut self) {
+        self.identity = None;
+    }
+}
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let identity = match self.identity {
+            Some(identity) => identity,
+            None => return,
+        };
+
+        match fs::symlink_metadata(&self.path) {
+            Ok(metadata) if (metadata.dev(), metadata.ino()) == identity => {
+                fs::remove_file(&self.path).ok();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl UnixConnection {
+    pub fn new(stream: UnixStream) -> Self {
+        let peer_credentials = read_peer_credentials(&stream);
+        UnixConnection {
+            stream,
+            peer_credentials,
+        }
+    }
+}
+
+impl Read for UnixConnection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for UnixConnection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Stream for UnixConnection {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.stream.set_write_timeout(timeout)
+    }
+
+    fn shutdown_write(&mut self) -> std::io::Result<()> {
+        self.stream.shutdown(Shutdown::Write)
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.peer_credentials
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_peer_credentials(stream: &UnixStream) -> Option<PeerCredentials> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length,
+        )
+    };
+
+    if result != 0 {
+        return None;
+    }
+
+    Some(PeerCredentials::new(
+        credentials.uid,
+        credentials.gid,
+        credentials.pid as u32,
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_peer_credentials(_stream: &UnixStream) -> Option<PeerCredentials> {
+    None
+}
@@ -0,0 +1,51 @@
+use super::{registry::Registry, socket::Listener};
+use std::{
+    io::{ErrorKind, Read, Write},
+    os::unix::{io::AsRawFd, net::UnixStream},
+};
+
+// Blocks the acceptor until a client is waiting on one of its listeners or the server shuts down
+pub struct Waiter {
+    wake: UnixStream,
+}
+
+impl Waiter {
+    // Shutting the registry down wakes the waiter through a socket pair
+    pub fn new(registry: &Registry) -> std::io::Result<Self> {
+        let (wake, sender) = UnixStream::pair()?;
+        wake.set_nonblocking(true)?;
+        sender.set_nonblocking(true)?;
+
+        registry.set_waker(move || {
+            (&sender).write_all(&[1]).ok();
+        });
+
+        Ok(Waiter { wake })
+    }
+
+    pub fn wait(&self, listeners: &[Listener]) -> std::io::Result<()> {
+        let mut fds: Vec<libc::pollfd> = listeners
+            .iter()
+            .map(Listener::as_raw_fd)
+            .chain(Some(self.wake.as_raw_fd()))
+            .map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } == -1 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() != ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+
+        // Empty the socket so the next wait blocks again
+        let mut buffer = [0; 64];
+        while let Ok(1..) = (&self.wake).read(&mut buffer) {}
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+#![cfg(target_os = "linux")]
+
+// Runs the process out of file descriptors, so these stay in a test binary of their own and take
+// turns
+
+use http::{
+    ErrorContext, HandleClientError, Request, Response, Server, ServerBuilder, ServerConfig, Status,
+};
+use std::{
+    fs,
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+mod common;
+
+use common::epoll_config;
+
+struct OkServer;
+
+impl Server for OkServer {
+    fn handle_request(&self, _request: Request) -> Response {
+        Response::new_status(Status::Ok, Some("fine".to_owned()))
+    }
+}
+
+static SERVER: OkServer = OkServer;
+static EXCLUSIVE: Mutex<()> = Mutex::new(());
+
+#[test]
+fn backs_off_when_out_of_descriptors() {
+    check_backoff(ServerConfig::new());
+}
+
+#[test]
+fn epoll_backs_off_when_out_of_descriptors() {
+    check_backoff(epoll_config());
+}
+
+fn check_backoff(config: ServerConfig) {
+    let _exclusive = EXCLUSIVE.lock().unwrap_or_else(|error| error.into_inner());
+
+    let errors = Arc::new(AtomicUsize::new(0));
+    let handle = {
+        let errors = errors.clone();
+        ServerBuilder::new(&SERVER)
+            .listener(TcpListener::bind("127.0.0.1:0").unwrap())
+            .config(config)
+            .client_error_callback(move |error: HandleClientError, _: &ErrorContext| {
+                if let HandleClientError::AcceptClientError(_) = error {
+                    errors.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .start()
+            .unwrap()
+    };
+    let addr = handle.local_addrs()[0];
+    request(addr);
+
+    // Leave room for only a few more descriptors, so the server fails to accept a waiting client
+    let limit = get_limit();
+    set_limit(fs::read_dir("/proc/self/fd").unwrap().count() as u64 + 4);
+    let clients: Vec<TcpStream> = (0..8)
+        .map_while(|_| TcpStream::connect(addr).ok())
+        .collect();
+    thread::sleep(Duration::from_millis(500));
+    set_limit(limit);
+    drop(clients);
+
+    // The error repeats while the client waits, but at the pace of the accept poll interval
+    let errors = errors.load(Ordering::SeqCst);
+    assert!(errors > 0);
+    assert!(errors < 20, "{} accept errors", errors);
+
+    // Accepting picks up again once descriptors are available
+    request(addr);
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn request(addr: SocketAddr) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 Ok\r\n"));
+}
+
+fn get_limit() -> u64 {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    assert_eq!(
+        unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) },
+        0
+    );
+    limit.rlim_cur
+}
+
+fn set_limit(soft: u64) {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    unsafe {
+        assert_eq!(libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit), 0);
+        limit.rlim_cur = soft;
+        assert_eq!(libc::setrlimit(libc::RLIMIT_NOFILE, &limit), 0);
+    }
+}
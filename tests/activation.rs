@@ -0,0 +1,189 @@
+#![cfg(unix)]
+
+use http::{Request, Response, Server, ServerBuilder, Status};
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    os::unix::{io::AsRawFd, net::UnixStream},
+    path::Path,
+    process::Command,
+    sync::mpsc::{self, Sender},
+    sync::Mutex,
+    time::Duration,
+};
+
+// Set for the copy of this test binary started as the replacement
+const REPLACEMENT: &str = "HTTP_TEST_REPLACEMENT";
+
+// Set for copies of this test binary checking what they inherit
+const INHERIT_CHECK: &str = "HTTP_TEST_INHERIT_CHECK";
+
+struct NamedServer {
+    name: &'static str,
+    served: Mutex<Option<Sender<()>>>,
+}
+
+impl Server for NamedServer {
+    fn handle_request(&self, _request: Request) -> Response {
+        if let Some(served) = &*self.served.lock().unwrap() {
+            served.send(()).ok();
+        }
+
+        Response::new_status(Status::Ok, Some(self.name.to_owned()))
+    }
+}
+
+static OLD: NamedServer = NamedServer {
+    name: "old",
+    served: Mutex::new(None),
+};
+static NEW: NamedServer = NamedServer {
+    name: "new",
+    served: Mutex::new(None),
+};
+
+fn get<S: Read + Write>(mut stream: S) -> String {
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response.split("\r\n\r\n").nth(1).unwrap().to_owned()
+}
+
+fn get_tcp(addr: SocketAddr) -> String {
+    let stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    get(stream)
+}
+
+fn get_unix(path: &Path) -> String {
+    let stream = UnixStream::connect(path).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    get(stream)
+}
+
+#[test]
+fn hands_listeners_to_replacement() {
+    let path = std::env::temp_dir().join(format!("http-activation-{}.sock", std::process::id()));
+    let mut handle = ServerBuilder::new(&OLD)
+        .bind("127.0.0.1:0".parse().unwrap())
+        .bind_unix(&path)
+        .start()
+        .unwrap();
+    let addr = handle.local_addrs()[0];
+    assert_eq!(get_tcp(addr), "old");
+
+    let mut replacement = handle
+        .spawn_replacement(
+            Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "replacement", "--nocapture"])
+                .env(REPLACEMENT, "1"),
+        )
+        .unwrap();
+    handle.shutdown(Duration::from_secs(1));
+
+    // The sockets stay open, and the socket file in place, for the replacement
+    assert_eq!(get_unix(&path), "new");
+    assert_eq!(get_tcp(addr), "new");
+
+    assert!(replacement.wait().unwrap().success());
+    std::fs::remove_file(&path).ok();
+}
+
+// Runs in the replacement process, serving two requests on the inherited listeners
+#[test]
+fn replacement() {
+    if std::env::var_os(REPLACEMENT).is_none() {
+        return;
+    }
+
+    let (sender, served) = mpsc::channel();
+    *NEW.served.lock().unwrap() = Some(sender);
+
+    let handle = ServerBuilder::new(&NEW)
+        .inherit_listeners()
+        .start()
+        .unwrap();
+    assert_eq!(handle.local_addrs().len(), 1);
+
+    // The variables name this process and stay set, but the sockets are only taken once
+    let pid = std::env::var("LISTEN_PID").unwrap();
+    assert_eq!(pid, std::process::id().to_string());
+    let other = ServerBuilder::new(&NEW)
+        .bind("127.0.0.1:0".parse().unwrap())
+        .inherit_listeners()
+        .start()
+        .unwrap();
+    assert_ne!(other.local_addrs(), handle.local_addrs());
+    other.shutdown(Duration::from_secs(1));
+
+    for _ in 0..2 {
+        served.recv_timeout(Duration::from_secs(10)).unwrap();
+    }
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn run_inherit_check(check: &str, env: &[(&str, &str)]) {
+    let status = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "inherit_check", "--nocapture"])
+        .env(INHERIT_CHECK, check)
+        .envs(env.iter().copied())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn ignores_listeners_meant_for_another_process() {
+    run_inherit_check("other-process", &[("LISTEN_FDS", "1"), ("LISTEN_PID", "1")]);
+}
+
+#[test]
+fn rejects_sockets_that_are_not_listening() {
+    run_inherit_check("not-listening", &[]);
+}
+
+// Runs in a copy of this test binary, which has no other tests running to share its environment
+#[test]
+fn inherit_check() {
+    let check = match std::env::var(INHERIT_CHECK) {
+        Ok(check) => check,
+        Err(_) => return,
+    };
+
+    match check.as_str() {
+        // Nothing is adopted, so the server binds instead
+        "other-process" => {
+            let handle = ServerBuilder::new(&NEW)
+                .bind("127.0.0.1:0".parse().unwrap())
+                .inherit_listeners()
+                .start()
+                .unwrap();
+            assert_eq!(get_tcp(handle.local_addrs()[0]), "new");
+            handle.shutdown(Duration::from_secs(1));
+        }
+        // A connected socket passed where a listener belongs
+        "not-listening" => {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let _accepted = listener.accept().unwrap();
+            assert_ne!(unsafe { libc::dup2(stream.as_raw_fd(), 3) }, -1);
+
+            std::env::set_var("LISTEN_FDS", "1");
+            std::env::set_var("LISTEN_PID", std::process::id().to_string());
+            let error = ServerBuilder::new(&NEW)
+                .inherit_listeners()
+                .start()
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+        _ => panic!("Unknown check {}", check),
+    }
+}
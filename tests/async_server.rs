@@ -0,0 +1,388 @@
+#![cfg(feature = "tokio")]
+
+use http::{
+    spawn_async, AsyncServer, AsyncServerHandle, HandleClientError, Request, Response,
+    ServerConfig, Status, StreamingBody,
+};
+use std::{
+    future::Future,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::{net::TcpListener, runtime, sync::oneshot, time};
+
+mod common;
+
+use common::{connect_to, is_closed, read_response};
+
+const PIECE_SIZE: usize = 64 * 1024;
+const BODY_SIZE: usize = 64 * 1024 * 1024;
+
+struct TestServer {
+    produced: Arc<AtomicUsize>,
+}
+
+impl AsyncServer for TestServer {
+    fn handle_request(&self, request: Request) -> impl Future<Output = Response> + Send {
+        let produced = self.produced.clone();
+        async move {
+            let uri = request.header().uri().to_owned();
+            match uri.as_str() {
+                "/slow" => time::sleep(Duration::from_millis(300)).await,
+                "/stuck" => time::sleep(Duration::from_secs(30)).await,
+                "/large" => return large_response(produced),
+                "/broken-stream" => return broken_response(),
+                _ => {}
+            }
+
+            let trailer = request.get_trailer("Checksum").unwrap_or("");
+            Response::new_status(
+                Status::Ok,
+                Some(format!("{}|{}|{}", uri, request.body(), trailer)),
+            )
+        }
+    }
+}
+
+// Streams a large body, counting how much of it has been produced
+fn large_response(produced: Arc<AtomicUsize>) -> Response {
+    Response::new_stream(
+        Status::Ok,
+        StreamingBody::new(move |writer| {
+            let piece = vec![b'a'; PIECE_SIZE];
+            for _ in 0..BODY_SIZE / PIECE_SIZE {
+                writer.write_all(&piece)?;
+                produced.fetch_add(PIECE_SIZE, Ordering::SeqCst);
+            }
+            Ok(())
+        }),
+    )
+}
+
+// Panics partway through its body
+fn broken_response() -> Response {
+    Response::new_stream(
+        Status::Ok,
+        StreamingBody::new(|writer| {
+            writer.write_all(b"partial")?;
+            panic!("broken stream");
+        }),
+    )
+}
+
+// A server running on a runtime of its own until it is shut down
+struct Running {
+    addr: SocketAddr,
+    handle: AsyncServerHandle,
+    produced: Arc<AtomicUsize>,
+    errors: mpsc::Receiver<HandleClientError>,
+    shutdown: oneshot::Sender<(AsyncServerHandle, Duration)>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Running {
+    fn start() -> Self {
+        Running::with_config(ServerConfig::new())
+    }
+
+    fn with_config(config: ServerConfig) -> Self {
+        let produced = Arc::new(AtomicUsize::new(0));
+        let server = TestServer {
+            produced: produced.clone(),
+        };
+
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let (sender, errors) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let handle = {
+            let _context = runtime.enter();
+            let listener = TcpListener::from_std(listener).unwrap();
+            let callback = move |error, _: &_| {
+                sender.lock().unwrap().send(error).ok();
+            };
+            spawn_async(listener, server, &config, Some(Box::new(callback))).unwrap()
+        };
+
+        // The runtime thread drives the server until it gets the handle back to shut it down
+        let (shutdown, stop) = oneshot::channel::<(AsyncServerHandle, Duration)>();
+        let thread = thread::spawn(move || {
+            runtime.block_on(async move {
+                if let Ok((handle, drain_timeout)) = stop.await {
+                    handle.shutdown(drain_timeout).await;
+                }
+            });
+        });
+
+        Running {
+            addr: handle.local_addr(),
+            handle,
+            produced,
+            errors,
+            shutdown,
+            thread,
+        }
+    }
+
+    fn connect(&self) -> TcpStream {
+        connect_to(self.addr)
+    }
+
+    // Shuts the server down in the background, returning a way to wait for it to finish
+    fn begin_shutdown(self, drain_timeout: Duration) -> thread::JoinHandle<()> {
+        assert!(self.shutdown.send((self.handle, drain_timeout)).is_ok());
+        self.thread
+    }
+}
+
+#[test]
+fn keeps_connections_open() {
+    let running = Running::start();
+    let mut stream = running.connect();
+
+    for uri in &["/first", "/second", "/third"] {
+        write!(stream, "GET {} HTTP/1.1\r\n\r\n", uri).unwrap();
+        let response = read_response(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 200 Ok\r\n"));
+        assert!(!response.contains("\r\nConnection:"));
+        assert!(response.ends_with(&format!("{}||", uri)));
+    }
+
+    running
+        .begin_shutdown(Duration::from_secs(1))
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn answers_pipelined_requests_in_order() {
+    let running = Running::start();
+    let mut stream = running.connect();
+
+    stream
+        .write_all(
+            b"GET /first HTTP/1.1\r\n\r\n\
+            POST /second HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody\
+            GET /third HTTP/1.1\r\n\r\n",
+        )
+        .unwrap();
+    assert!(read_response(&mut stream).ends_with("/first||"));
+    assert!(read_response(&mut stream).ends_with("/second|body|"));
+    assert!(read_response(&mut stream).ends_with("/third||"));
+
+    running
+        .begin_shutdown(Duration::from_secs(1))
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn reads_chunked_uploads() {
+    let running = Running::start();
+    let mut stream = running.connect();
+
+    stream
+        .write_all(
+            b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            4;ext\r\nWiki\r\n5\r\npedia\r\n0\r\nChecksum: abc\r\n\r\n",
+        )
+        .unwrap();
+    assert!(read_response(&mut stream).ends_with("/upload|Wikipedia|abc"));
+
+    // The connection stays usable after the last chunk
+    stream.write_all(b"GET /next HTTP/1.1\r\n\r\n").unwrap();
+    assert!(read_response(&mut stream).ends_with("/next||"));
+
+    running
+        .begin_shutdown(Duration::from_secs(1))
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn streams_with_backpressure() {
+    let running = Running::start();
+    let mut stream = running.connect();
+
+    stream
+        .write_all(b"GET /large HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    // A client that isn't reading holds the producer back once the socket buffers fill up
+    thread::sleep(Duration::from_millis(300));
+    let held_back = running.produced.load(Ordering::SeqCst);
+    assert!(held_back < BODY_SIZE / 4, "produced {} bytes", held_back);
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    assert!(response.starts_with(b"HTTP/1.1 200 Ok\r\n"));
+    assert!(response.ends_with(b"\r\n0\r\n\r\n"));
+    assert!(response.len() > BODY_SIZE);
+    assert_eq!(running.produced.load(Ordering::SeqCst), BODY_SIZE);
+
+    running
+        .begin_shutdown(Duration::from_secs(1))
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn finishes_requests_in_flight_on_shutdown() {
+    let running = Running::start();
+    let addr = running.addr;
+
+    let mut idle = running.connect();
+    idle.write_all(b"GET /first HTTP/1.1\r\n\r\n").unwrap();
+    read_response(&mut idle);
+
+    let mut busy = running.connect();
+    busy.write_all(b"GET /slow HTTP/1.1\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let stopped = running.begin_shutdown(Duration::from_secs(5));
+
+    // Idle connections close right away, while the request in flight still gets its response
+    assert!(is_closed(&mut idle));
+    let response = read_response(&mut busy);
+    assert!(response.ends_with("/slow||"));
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(is_closed(&mut busy));
+
+    stopped.join().unwrap();
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn drops_stuck_requests_after_drain_timeout() {
+    let running = Running::start();
+
+    let mut stream = running.connect();
+    stream.write_all(b"GET /stuck HTTP/1.1\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let start = Instant::now();
+    running
+        .begin_shutdown(Duration::from_millis(200))
+        .join()
+        .unwrap();
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(is_closed(&mut stream));
+}
+
+#[test]
+fn sheds_connections_over_limit() {
+    let mut config = ServerConfig::new();
+    config
+        .set_max_connections(1)
+        .set_retry_after(Duration::from_millis(1500));
+    let running = Running::with_config(config);
+
+    // An idle connection takes the only slot
+    let mut first = running.connect();
+    first.write_all(b"GET /first HTTP/1.1\r\n\r\n").unwrap();
+    read_response(&mut first);
+
+    let mut shed = running.connect();
+    let mut response = String::new();
+    shed.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    assert!(response.contains("\r\nRetry-After: 2\r\n"));
+
+    let stats = running.handle.load_stats();
+    assert_eq!(stats.shed_at_connection_limit(), 1);
+    assert_eq!(stats.shed_at_queue_limit(), 0);
+    assert_eq!(stats.open_connections(), 1);
+
+    running
+        .begin_shutdown(Duration::from_secs(1))
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn closes_before_requests_queued_over_limit() {
+    let mut config = ServerConfig::new();
+    config.set_max_pipelined_requests(0);
+    let running = Running::with_config(config);
+    let mut stream = running.connect();
+
+    // Requests sent one after another keep the connection open
+    for uri in &["/first", "/second"] {
+        write!(stream, "GET {} HTTP/1.1\r\n\r\n", uri).unwrap();
+        let response = read_response(&mut stream);
+        assert!(!response.contains("\r\nConnection:"));
+    }
+
+    // A request queued behind another one goes unanswered
+    stream
+        .write_all(b"GET /third HTTP/1.1\r\n\r\nGET /fourth HTTP/1.1\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert!(response.ends_with("/third||"));
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(is_closed(&mut stream));
+
+    running
+        .begin_shutdown(Duration::from_secs(1))
+        .join()
+        .unwrap();
+}
+
+// A clean close lets the connection go right away, even while the client keeps its side open
+#[test]
+fn closing_does_not_hold_connection() {
+    let running = Running::start();
+    let mut stream = running.connect();
+
+    stream
+        .write_all(b"GET /first HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    read_response(&mut stream);
+    assert!(is_closed(&mut stream));
+
+    let start = Instant::now();
+    while running.handle.load_stats().open_connections() > 0 {
+        assert!(start.elapsed() < Duration::from_millis(250));
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    running
+        .begin_shutdown(Duration::from_secs(1))
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn reports_panicking_stream_as_handler_panic() {
+    let running = Running::start();
+    let mut stream = running.connect();
+
+    stream
+        .write_all(b"GET /broken-stream HTTP/1.1\r\n\r\n")
+        .unwrap();
+
+    // Whatever was sent of the response, it never ends
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    assert!(!response.ends_with(b"\r\n0\r\n\r\n"));
+
+    match running.errors.recv_timeout(Duration::from_secs(5)).unwrap() {
+        HandleClientError::HandlerPanic(message) => assert_eq!(message, "broken stream"),
+        error => panic!("unexpected error {}", error),
+    }
+
+    running
+        .begin_shutdown(Duration::from_secs(1))
+        .join()
+        .unwrap();
+}
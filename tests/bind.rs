@@ -0,0 +1,72 @@
+#![cfg(unix)]
+
+use http::{Request, Response, Server, ServerBuilder, Status};
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+struct OkServer;
+
+impl Server for OkServer {
+    fn handle_request(&self, _request: Request) -> Response {
+        Response::new_status(Status::Ok, Some("hello".to_owned()))
+    }
+}
+
+static SERVER: OkServer = OkServer;
+
+fn get(addr: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn unspecified_ipv6_address_accepts_both_families() {
+    let handle = match ServerBuilder::new(&SERVER)
+        .bind("[::]:0".parse().unwrap())
+        .start()
+    {
+        Ok(handle) => handle,
+        // Nothing to check on hosts without IPv6
+        Err(error) if error.kind() == ErrorKind::AddrNotAvailable => return,
+        Err(error) => panic!("{}", error),
+    };
+    let port = handle.local_addrs()[0].port();
+
+    let response = get(SocketAddr::from(([127, 0, 0, 1], port)));
+    assert!(response.ends_with("\r\n\r\nhello"));
+
+    let response = get(SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], port)));
+    assert!(response.ends_with("\r\n\r\nhello"));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+#[test]
+fn serves_every_bound_address() {
+    let handle = ServerBuilder::new(&SERVER)
+        .bind("127.0.0.1:0".parse().unwrap())
+        .bind("127.0.0.1:0".parse().unwrap())
+        .start()
+        .unwrap();
+    let addrs = handle.local_addrs().to_vec();
+    assert_eq!(addrs.len(), 2);
+    assert_ne!(addrs[0].port(), addrs[1].port());
+
+    for addr in addrs {
+        assert!(get(addr).ends_with("\r\n\r\nhello"));
+    }
+
+    handle.shutdown(Duration::from_secs(1));
+}
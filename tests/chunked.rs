@@ -0,0 +1,155 @@
+use http::{serve_connection, Request, Response, Server, ServerConfig, Status};
+
+mod common;
+
+use common::Pipe;
+
+struct EchoServer;
+
+impl Server for EchoServer {
+    fn handle_request(&self, request: Request) -> Response {
+        Response::new_status(
+            Status::Ok,
+            Some(format!(
+                "{}|{}",
+                request.body(),
+                request.get_trailer("Checksum").unwrap_or("")
+            )),
+        )
+    }
+}
+
+fn serve(input: &[u8], read_size: usize, config: &ServerConfig) -> String {
+    let mut pipe = Pipe::new(input, read_size);
+    serve_connection(&mut pipe, &EchoServer, config).ok();
+    String::from_utf8(pipe.output).unwrap()
+}
+
+fn chunked_request(body: &str) -> String {
+    format!(
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n{}",
+        body
+    )
+}
+
+#[test]
+fn ignores_chunk_extensions() {
+    let request =
+        chunked_request("4;name=value\r\nWiki\r\n5;a;b=\"c;d\"\r\npedia\r\n0;last\r\n\r\n");
+    let output = serve(request.as_bytes(), usize::MAX, &ServerConfig::new());
+    assert!(output.starts_with("HTTP/1.1 200 Ok\r\n"));
+    assert!(output.ends_with("\r\n\r\nWikipedia|"));
+}
+
+#[test]
+fn reads_trailers() {
+    let request = chunked_request("4\r\nWiki\r\n0\r\nChecksum: abc\r\nOther: 1\r\n\r\n");
+    let output = serve(request.as_bytes(), usize::MAX, &ServerConfig::new());
+    assert!(output.ends_with("\r\n\r\nWiki|abc"));
+}
+
+#[test]
+fn reads_split_trailers_without_a_header_limit() {
+    let mut config = ServerConfig::new();
+    config.set_max_header_size(usize::MAX);
+
+    let request = chunked_request("4\r\nWiki\r\n0\r\nChecksum: abc\r\n\r\n");
+    let output = serve(request.as_bytes(), 3, &config);
+    assert!(output.ends_with("\r\n\r\nWiki|abc"), "got {}", output);
+}
+
+#[test]
+fn decodes_input_split_anywhere() {
+    let request = chunked_request(
+        "4;ext\r\nWiki\r\n5\r\npedia\r\nA\r\n in chunks\r\n0\r\nChecksum: abc\r\n\r\n",
+    );
+
+    for read_size in 1..8 {
+        let output = serve(request.as_bytes(), read_size, &ServerConfig::new());
+        assert!(
+            output.ends_with("\r\n\r\nWikipedia in chunks|abc"),
+            "read size {} got {}",
+            read_size,
+            output
+        );
+    }
+}
+
+#[test]
+fn rejects_invalid_chunk_sizes() {
+    for size in &["zz", "", "-1", "0x4", "4 4"] {
+        let request = chunked_request(&format!("{}\r\nWiki\r\n0\r\n\r\n", size));
+        let output = serve(request.as_bytes(), usize::MAX, &ServerConfig::new());
+        assert!(
+            output.starts_with("HTTP/1.1 400 "),
+            "size {:?} got {}",
+            size,
+            output
+        );
+    }
+}
+
+#[test]
+fn rejects_chunk_sizes_that_overflow() {
+    let request = chunked_request("1ffffffffffffffffffffffff\r\nWiki\r\n0\r\n\r\n");
+    let output = serve(request.as_bytes(), usize::MAX, &ServerConfig::new());
+    assert!(output.starts_with("HTTP/1.1 413 "));
+}
+
+#[test]
+fn limits_total_body_size() {
+    let mut config = ServerConfig::new();
+    config.set_max_body_size(8);
+
+    // The limit counts every chunk together
+    let request = chunked_request("4\r\nWiki\r\n4\r\npedi\r\n0\r\n\r\n");
+    let output = serve(request.as_bytes(), usize::MAX, &config);
+    assert!(output.ends_with("\r\n\r\nWikipedi|"));
+
+    let request = chunked_request("4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n");
+    let output = serve(request.as_bytes(), usize::MAX, &config);
+    assert!(output.starts_with("HTTP/1.1 413 "));
+}
+
+#[test]
+fn prefers_transfer_encoding_over_content_length() {
+    let request = b"POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n\
+        4\r\nWiki\r\n0\r\n\r\n\
+        GET /smuggled HTTP/1.1\r\n\r\n";
+    let output = serve(request, usize::MAX, &ServerConfig::new());
+
+    // The body is read as chunks, and nothing after such an ambiguous request is trusted
+    assert!(output.ends_with("\r\n\r\nWiki|"));
+    assert!(output.contains("\r\nConnection: close\r\n"));
+    assert_eq!(output.matches("HTTP/1.1 ").count(), 1);
+}
+
+#[test]
+fn rejects_conflicting_content_lengths() {
+    let request = b"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 5\r\n\r\nabcde";
+    let output = serve(request, usize::MAX, &ServerConfig::new());
+    assert!(output.starts_with("HTTP/1.1 400 "));
+    assert_eq!(output.matches("HTTP/1.1 ").count(), 1);
+
+    // Repeating the same length is harmless
+    let request = b"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 3\r\n\r\nabc";
+    let output = serve(request, usize::MAX, &ServerConfig::new());
+    assert!(output.ends_with("\r\n\r\nabc|"));
+}
+
+#[test]
+fn rejects_signed_content_length() {
+    let request = b"POST / HTTP/1.1\r\nContent-Length: +3\r\n\r\nabc";
+    let output = serve(request, usize::MAX, &ServerConfig::new());
+    assert!(output.starts_with("HTTP/1.1 400 "));
+    assert_eq!(output.matches("HTTP/1.1 ").count(), 1);
+}
+
+#[test]
+fn rejects_whitespace_before_colon() {
+    let request = b"POST / HTTP/1.1\r\nTransfer-Encoding : chunked\r\nContent-Length: 3\r\n\r\n\
+        4\r\nWiki\r\n0\r\n\r\n";
+    let output = serve(request, usize::MAX, &ServerConfig::new());
+    assert!(output.starts_with("HTTP/1.1 400 "));
+    assert_eq!(output.matches("HTTP/1.1 ").count(), 1);
+}
@@ -0,0 +1,128 @@
+use http::{
+    ErrorContext, HandleClientError, Request, Response, Server, ServerBuilder, ServerConfig, Status,
+};
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver},
+        Mutex,
+    },
+    time::Duration,
+};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+
+struct PanickingServer;
+
+impl Server for PanickingServer {
+    fn handle_request(&self, request: Request) -> Response {
+        if request.header().uri() == "/panic" {
+            panic!("boom");
+        }
+
+        Response::new_status(Status::Ok, Some("fine".to_owned()))
+    }
+}
+
+static SERVER: PanickingServer = PanickingServer;
+
+struct Report {
+    error: String,
+    connection_id: Option<u64>,
+    peer_addr: Option<SocketAddr>,
+    request_line: Option<String>,
+    bytes_read: u64,
+}
+
+// Starts a server with a single worker, so a worker lost to a panic would stop it serving
+fn start(mut config: ServerConfig) -> (http::ServerHandle, Receiver<Report>) {
+    config.set_worker_count(1);
+
+    let (sender, reports) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    let handle = ServerBuilder::new(&SERVER)
+        .listener(TcpListener::bind("127.0.0.1:0").unwrap())
+        .config(config)
+        .client_error_callback(move |error: HandleClientError, context: &ErrorContext| {
+            let report = Report {
+                error: error.to_string(),
+                connection_id: context.connection_id(),
+                peer_addr: context.peer_addr(),
+                request_line: context.request_line().map(str::to_owned),
+                bytes_read: context.bytes_read(),
+            };
+            sender.lock().unwrap().send(report).ok();
+        })
+        .start()
+        .unwrap();
+
+    (handle, reports)
+}
+
+// Sends a request and reads until the server closes, returning the client address and response
+fn send(addr: SocketAddr, request: &str) -> (SocketAddr, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok();
+    (stream.local_addr().unwrap(), response)
+}
+
+#[test]
+fn reports_errors_with_connection_context() {
+    check_reports(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_reports_errors_with_connection_context() {
+    check_reports(epoll_config());
+}
+
+fn check_reports(config: ServerConfig) {
+    let (handle, reports) = start(config);
+    let addr = handle.local_addrs()[0];
+
+    // A body that can't be read is reported along with the request it belongs to
+    let request = "POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n";
+    let (client, response) = send(addr, request);
+    assert!(response.starts_with("HTTP/1.1 400 "));
+
+    let report = reports.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(report.error.starts_with("Failed to read request"));
+    assert_eq!(report.peer_addr, Some(client));
+    let first_id = report.connection_id.unwrap();
+    assert_eq!(
+        report.request_line.as_deref(),
+        Some("POST /upload HTTP/1.1")
+    );
+    assert_eq!(report.bytes_read, request.len() as u64);
+
+    // So is a panicking handler, whose client gets a 500 before the connection closes
+    let request = "GET /panic HTTP/1.1\r\n\r\n";
+    let (client, response) = send(addr, request);
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(response.contains("\r\nConnection: close\r\n"));
+
+    let report = reports.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(report.error, "Handler panicked (boom)");
+    assert_eq!(report.peer_addr, Some(client));
+    assert_ne!(report.connection_id, Some(first_id));
+    assert!(report.connection_id.is_some());
+    assert_eq!(report.request_line.as_deref(), Some("GET /panic HTTP/1.1"));
+    assert_eq!(report.bytes_read, request.len() as u64);
+
+    // The only worker is still serving
+    let (_, response) = send(addr, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\nfine"));
+
+    handle.shutdown(Duration::from_secs(1));
+}
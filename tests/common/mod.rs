@@ -0,0 +1,112 @@
+// Helpers shared by the integration tests, each test binary only uses some of them
+#![allow(dead_code)]
+
+use http::{Server, ServerBuilder, ServerConfig, ServerHandle};
+use std::{
+    io::{Cursor, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+#[cfg(target_os = "linux")]
+pub fn epoll_config() -> ServerConfig {
+    let mut config = ServerConfig::new();
+    config.set_backend(http::Backend::Epoll);
+    config
+}
+
+// Starts a server on a local port
+pub fn serve<S: Server + 'static>(server: S, config: ServerConfig) -> ServerHandle {
+    ServerBuilder::new(server)
+        .listener(TcpListener::bind("127.0.0.1:0").unwrap())
+        .config(config)
+        .start()
+        .unwrap()
+}
+
+// Starts a server on a local port along with a client connected to it
+pub fn start<S: Server + 'static>(server: S, config: ServerConfig) -> (ServerHandle, TcpStream) {
+    let handle = serve(server, config);
+    let stream = connect(&handle);
+    (handle, stream)
+}
+
+pub fn connect(handle: &ServerHandle) -> TcpStream {
+    connect_to(handle.local_addrs()[0])
+}
+
+// Connects a client that gives up on reads the server never answers
+pub fn connect_to(addr: SocketAddr) -> TcpStream {
+    let stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+// Reads a single response, using its Content-Length to find where it ends
+pub fn read_response(stream: &mut TcpStream) -> String {
+    let mut response = Vec::new();
+    let mut byte = [0];
+    while !response.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        response.push(byte[0]);
+    }
+
+    let header = String::from_utf8(response.clone()).unwrap();
+    let length: usize = header
+        .split("\r\n")
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .map_or(0, |length| length.parse().unwrap());
+
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).unwrap();
+    response.extend(body);
+    String::from_utf8(response).unwrap()
+}
+
+// Reads until the server closes the connection
+pub fn read_all(stream: &mut TcpStream) -> String {
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    String::from_utf8(response).unwrap()
+}
+
+// True once the server has closed its side
+pub fn is_closed(stream: &mut TcpStream) -> bool {
+    matches!(stream.read(&mut [0]), Ok(0) | Err(_))
+}
+
+// Replays input at most read_size bytes at a time and collects everything written back
+pub struct Pipe {
+    input: Cursor<Vec<u8>>,
+    read_size: usize,
+    pub output: Vec<u8>,
+}
+
+impl Pipe {
+    pub fn new(input: &[u8], read_size: usize) -> Self {
+        Pipe {
+            input: Cursor::new(input.to_vec()),
+            read_size,
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let length = buf.len().min(self.read_size);
+        self.input.read(&mut buf[..length])
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use http::{Request, Response, Server, ServerBuilder, ServerConfig, Status};
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+
+struct ConnectionServer;
+
+impl Server for ConnectionServer {
+    fn handle_request(&self, request: Request) -> Response {
+        Response::new_status(
+            Status::Ok,
+            Some(format!(
+                "{} {} {} {}",
+                request.peer_addr().unwrap(),
+                request.local_addr().unwrap(),
+                request.connection_id(),
+                request.request_index()
+            )),
+        )
+    }
+}
+
+static SERVER: ConnectionServer = ConnectionServer;
+
+// Sends requests on one connection, returning the client address and the response bodies
+fn send(addr: std::net::SocketAddr, count: usize) -> (String, Vec<Vec<String>>) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    let mut requests = "GET / HTTP/1.1\r\n\r\n".repeat(count - 1);
+    requests.push_str("GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+    stream.write_all(requests.as_bytes()).unwrap();
+
+    let mut responses = String::new();
+    stream.read_to_string(&mut responses).unwrap();
+
+    let bodies = responses
+        .split("HTTP/1.1 ")
+        .skip(1)
+        .map(|response| {
+            let body = response.split("\r\n\r\n").nth(1).unwrap();
+            body.split(' ').map(str::to_owned).collect()
+        })
+        .collect();
+    (stream.local_addr().unwrap().to_string(), bodies)
+}
+
+#[test]
+fn exposes_connection_details() {
+    check_connection_details(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_exposes_connection_details() {
+    check_connection_details(epoll_config());
+}
+
+fn check_connection_details(config: ServerConfig) {
+    let handle = ServerBuilder::new(&SERVER)
+        .listener(TcpListener::bind("127.0.0.1:0").unwrap())
+        .config(config)
+        .start()
+        .unwrap();
+    let local_addr = handle.local_addrs()[0];
+
+    let (client, first) = send(local_addr, 2);
+    assert_eq!(first.len(), 2);
+    for (index, body) in first.iter().enumerate() {
+        assert_eq!(body[0], client);
+        assert_eq!(body[1], local_addr.to_string());
+        assert_eq!(body[2], first[0][2]);
+        assert_eq!(body[3], index.to_string());
+    }
+
+    // Each connection has an id of its own
+    let (_, second) = send(local_addr, 1);
+    assert_ne!(second[0][2], first[0][2]);
+    assert_eq!(second[0][3], "0");
+
+    handle.shutdown(Duration::from_secs(1));
+}
@@ -0,0 +1,144 @@
+use http::{Request, RequestHeader, Response, Server, ServerConfig, Status};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+use common::{read_all, start};
+
+struct UploadServer;
+
+impl Server for UploadServer {
+    fn handle_request(&self, request: Request) -> Response {
+        Response::new_status(Status::Ok, Some(request.body().to_owned()))
+    }
+
+    fn check_continue(&self, header: &RequestHeader) -> Option<Response> {
+        match header.uri() {
+            "/full" => Some(Response::new_status(Status::RequestEntityTooLarge, None)),
+            _ => None,
+        }
+    }
+}
+
+static SERVER: UploadServer = UploadServer;
+
+const CONTINUE: &str = "HTTP/1.1 100 Continue\r\n\r\n";
+
+fn read_exact(stream: &mut TcpStream, length: usize) -> String {
+    let mut buffer = vec![0; length];
+    stream.read_exact(&mut buffer).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+#[test]
+fn sends_continue_before_reading_body() {
+    check_continue(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_sends_continue_before_reading_body() {
+    check_continue(epoll_config());
+}
+
+#[test]
+fn skips_continue_once_body_arrives() {
+    check_skipped_continue(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_skips_continue_once_body_arrives() {
+    check_skipped_continue(epoll_config());
+}
+
+#[test]
+fn rejects_unknown_expectations() {
+    check_unknown_expectation(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_rejects_unknown_expectations() {
+    check_unknown_expectation(epoll_config());
+}
+
+#[test]
+fn lets_server_reject_before_body() {
+    check_rejected_upload(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_lets_server_reject_before_body() {
+    check_rejected_upload(epoll_config());
+}
+
+fn check_continue(config: ServerConfig) {
+    let (handle, mut stream) = start(&SERVER, config);
+
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\
+            Connection: close\r\n\r\n",
+        )
+        .unwrap();
+    assert_eq!(read_exact(&mut stream, CONTINUE.len()), CONTINUE);
+
+    stream.write_all(b"body").unwrap();
+    let response = read_all(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 200 Ok\r\n"));
+    assert!(response.ends_with("\r\n\r\nbody"));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+// A client that sends its body without waiting doesn't need to be told to
+fn check_skipped_continue(config: ServerConfig) {
+    let (handle, mut stream) = start(&SERVER, config);
+
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\
+            Connection: close\r\n\r\nbody",
+        )
+        .unwrap();
+    let response = read_all(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 200 Ok\r\n"));
+    assert!(response.ends_with("\r\n\r\nbody"));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn check_unknown_expectation(config: ServerConfig) {
+    let (handle, mut stream) = start(&SERVER, config);
+
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nExpect: teapot\r\nContent-Length: 4\r\n\r\n")
+        .unwrap();
+    let response = read_all(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 417 "));
+    assert!(response.contains("\r\nConnection: close\r\n"));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+// The final status goes out instead of 100, and the connection closes since the body may follow
+fn check_rejected_upload(config: ServerConfig) {
+    let (handle, mut stream) = start(&SERVER, config);
+
+    stream
+        .write_all(b"POST /full HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\r\n")
+        .unwrap();
+    let response = read_all(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 413 "));
+    assert!(response.contains("\r\nConnection: close\r\n"));
+
+    handle.shutdown(Duration::from_secs(1));
+}
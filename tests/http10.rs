@@ -0,0 +1,113 @@
+use http::{Request, Response, Server, ServerConfig, Status};
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+use common::{connect, is_closed, read_response, start};
+
+struct VersionServer;
+
+impl Server for VersionServer {
+    fn handle_request(&self, request: Request) -> Response {
+        Response::new_status(Status::Ok, Some(request.version().to_string()))
+    }
+}
+
+static SERVER: VersionServer = VersionServer;
+
+#[test]
+fn closes_http10_connections_by_default() {
+    check_default_close(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_closes_http10_connections_by_default() {
+    check_default_close(epoll_config());
+}
+
+#[test]
+fn keeps_http10_connections_open_on_request() {
+    check_keep_alive(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_keeps_http10_connections_open_on_request() {
+    check_keep_alive(epoll_config());
+}
+
+#[test]
+fn rejects_unsupported_versions() {
+    check_unsupported_versions(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_rejects_unsupported_versions() {
+    check_unsupported_versions(epoll_config());
+}
+
+fn check_default_close(config: ServerConfig) {
+    let (handle, mut stream) = start(&SERVER, config);
+
+    stream.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    let response = read_response(&mut stream);
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(response.ends_with("\r\n\r\nHTTP/1.0"));
+    assert!(is_closed(&mut stream));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn check_keep_alive(config: ServerConfig) {
+    let (handle, mut stream) = start(&SERVER, config);
+
+    for _ in 0..2 {
+        stream
+            .write_all(b"GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n")
+            .unwrap();
+        let response = read_response(&mut stream);
+        assert!(response.contains("\r\nConnection: keep-alive\r\n"));
+        assert!(response.ends_with("\r\n\r\nHTTP/1.0"));
+    }
+
+    // Leaving the header out of a later request ends the connection
+    stream.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    let response = read_response(&mut stream);
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(is_closed(&mut stream));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+// Versions that are well-formed but not spoken get a 505, malformed ones a 400
+fn check_unsupported_versions(config: ServerConfig) {
+    let (handle, _) = start(&SERVER, config);
+
+    for (version, status) in &[
+        ("HTTP/2.0", "505"),
+        ("HTTP/1.2", "505"),
+        ("HTTP/1", "400"),
+        ("HTTX/1.1", "400"),
+    ] {
+        let mut stream = connect(&handle);
+        write!(stream, "GET / {}\r\n\r\n", version).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).ok();
+        assert!(
+            response.starts_with(&format!("HTTP/1.1 {} ", status)),
+            "{} got {}",
+            version,
+            response
+        );
+    }
+
+    handle.shutdown(Duration::from_secs(1));
+}
@@ -0,0 +1,186 @@
+use http::{OverflowPolicy, Request, Response, Server, ServerConfig, Status};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+use common::{connect, serve};
+
+struct OkServer;
+
+impl Server for OkServer {
+    fn handle_request(&self, _request: Request) -> Response {
+        Response::new_status(Status::Ok, None)
+    }
+}
+
+static SERVER: OkServer = OkServer;
+
+// Sends a request on a connection that stays open, reading its bodyless response
+fn get_keep_alive(stream: &mut TcpStream) -> String {
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+
+    let mut response = Vec::new();
+    let mut byte = [0];
+    while !response.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        response.push(byte[0]);
+    }
+    String::from_utf8(response).unwrap()
+}
+
+fn get(mut stream: TcpStream) -> String {
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok();
+    response
+}
+
+#[test]
+fn sheds_connections_over_limit() {
+    check_connection_limit(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_sheds_connections_over_limit() {
+    check_connection_limit(epoll_config());
+}
+
+fn check_connection_limit(mut config: ServerConfig) {
+    config
+        .set_max_connections(1)
+        .set_retry_after(Duration::from_secs(5));
+    let handle = serve(&SERVER, config);
+
+    // An idle connection takes the only slot
+    let first = connect(&handle);
+
+    let response = get(connect(&handle));
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    assert!(response.contains("Retry-After: 5\r\n"));
+    assert!(response.contains("Connection: close\r\n"));
+
+    let stats = handle.load_stats();
+    assert_eq!(stats.shed_at_connection_limit(), 1);
+    assert_eq!(stats.shed_at_queue_limit(), 0);
+    assert_eq!(stats.open_connections(), 1);
+
+    // The slot frees up once the first client is done
+    assert!(get(first).starts_with("HTTP/1.1 200 Ok\r\n"));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while handle.load_stats().open_connections() > 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(get(connect(&handle)).starts_with("HTTP/1.1 200 Ok\r\n"));
+    assert_eq!(handle.load_stats().shed_total(), 1);
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+// Fractions of a second round up, and never down to an immediate retry
+#[test]
+fn rounds_retry_after_up_to_whole_seconds() {
+    for (retry_after, header) in &[
+        (Duration::from_millis(250), "Retry-After: 1\r\n"),
+        (Duration::from_millis(1500), "Retry-After: 2\r\n"),
+        (Duration::from_secs(0), "Retry-After: 1\r\n"),
+    ] {
+        let mut config = ServerConfig::new();
+        config.set_max_connections(1).set_retry_after(*retry_after);
+        let handle = serve(&SERVER, config);
+
+        let _first = connect(&handle);
+        let response = get(connect(&handle));
+        assert!(
+            response.contains(header),
+            "{:?} got {}",
+            retry_after,
+            response
+        );
+
+        handle.shutdown(Duration::from_secs(1));
+    }
+}
+
+// The threaded backend gives each open connection a worker, so once the only worker is held by an
+// idle client, later ones fill the queue and the rest are turned away
+#[test]
+fn sheds_connections_over_queue_depth() {
+    for queue_depth in 1..=2 {
+        let mut config = ServerConfig::new();
+        config
+            .set_worker_count(1)
+            .set_queue_depth(queue_depth)
+            .set_overflow_policy(OverflowPolicy::Reject)
+            .set_retry_after(Duration::from_secs(3));
+        let handle = serve(&SERVER, config);
+
+        let mut first = connect(&handle);
+        assert!(get_keep_alive(&mut first).starts_with("HTTP/1.1 200 Ok\r\n"));
+
+        let queued: Vec<TcpStream> = (0..queue_depth).map(|_| connect(&handle)).collect();
+        let response = get(connect(&handle));
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(response.contains("Retry-After: 3\r\n"));
+
+        let stats = handle.load_stats();
+        assert_eq!(stats.shed_at_queue_limit(), 1);
+        assert_eq!(stats.shed_at_connection_limit(), 0);
+
+        // Queued clients are served in turn once the worker is free
+        assert!(get(first).starts_with("HTTP/1.1 200 Ok\r\n"));
+        for stream in queued {
+            assert!(get(stream).starts_with("HTTP/1.1 200 Ok\r\n"));
+        }
+
+        handle.shutdown(Duration::from_secs(1));
+    }
+}
+
+#[test]
+fn blocks_connections_over_queue_depth() {
+    let mut config = ServerConfig::new();
+    config
+        .set_worker_count(1)
+        .set_queue_depth(1)
+        .set_overflow_policy(OverflowPolicy::Block);
+    let handle = serve(&SERVER, config);
+
+    let mut first = connect(&handle);
+    assert!(get_keep_alive(&mut first).starts_with("HTTP/1.1 200 Ok\r\n"));
+    let queued = connect(&handle);
+
+    // A client over the limit waits rather than being turned away
+    let mut blocked = connect(&handle);
+    blocked
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    blocked
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    assert!(blocked.read(&mut [0]).is_err());
+    assert_eq!(handle.load_stats().shed_total(), 0);
+
+    assert!(get(first).starts_with("HTTP/1.1 200 Ok\r\n"));
+    assert!(get(queued).starts_with("HTTP/1.1 200 Ok\r\n"));
+
+    blocked
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut response = String::new();
+    blocked.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 Ok\r\n"));
+    assert_eq!(handle.load_stats().shed_total(), 0);
+
+    handle.shutdown(Duration::from_secs(1));
+}
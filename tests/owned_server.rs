@@ -0,0 +1,83 @@
+use http::{Request, Response, Server, ServerBuilder, ServerConfig, Status};
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+
+// Holds configuration only known at runtime, so it can't be a static
+struct GreetingServer {
+    greeting: String,
+}
+
+impl Server for GreetingServer {
+    fn handle_request(&self, _request: Request) -> Response {
+        Response::new_status(Status::Ok, Some(self.greeting.clone()))
+    }
+}
+
+fn get(addr: std::net::SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response.split("\r\n\r\n").nth(1).unwrap().to_owned()
+}
+
+#[test]
+fn serves_owned_server() {
+    let handle = ServerBuilder::new(GreetingServer {
+        greeting: format!("hello from {}", std::process::id()),
+    })
+    .listener(TcpListener::bind("127.0.0.1:0").unwrap())
+    .start()
+    .unwrap();
+
+    assert_eq!(
+        get(handle.local_addrs()[0]),
+        format!("hello from {}", std::process::id())
+    );
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+#[test]
+fn drops_shared_server_after_shutdown() {
+    check_drops_server(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_drops_shared_server_after_shutdown() {
+    check_drops_server(epoll_config());
+}
+
+fn check_drops_server(config: ServerConfig) {
+    let server = Arc::new(GreetingServer {
+        greeting: "hello".to_owned(),
+    });
+    let handle = ServerBuilder::new(server.clone())
+        .listener(TcpListener::bind("127.0.0.1:0").unwrap())
+        .config(config)
+        .start()
+        .unwrap();
+
+    assert_eq!(get(handle.local_addrs()[0]), "hello");
+    assert!(Arc::strong_count(&server) > 1);
+
+    // Every thread holding the server has exited
+    handle.shutdown(Duration::from_secs(1));
+    assert_eq!(Arc::strong_count(&server), 1);
+}
@@ -0,0 +1,175 @@
+use http::{Request, Response, Server, ServerConfig, Status};
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+use common::{connect, is_closed, read_response, start};
+
+struct CloseServer;
+
+impl Server for CloseServer {
+    fn handle_request(&self, request: Request) -> Response {
+        let mut response =
+            Response::new_status(Status::Ok, Some(request.header().uri().to_owned()));
+
+        // The handler can end the connection too, naming the header in any case
+        match request.header().uri() {
+            "/last" => response
+                .header_mut()
+                .insert_header("Connection".to_owned(), "close".to_owned()),
+            "/lowercase" => response
+                .header_mut()
+                .insert_header("connection".to_owned(), "close".to_owned()),
+            _ => {}
+        }
+
+        response
+    }
+}
+
+static SERVER: CloseServer = CloseServer;
+
+#[test]
+fn keeps_http11_connections_open_by_default() {
+    check_default_persistence(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_keeps_http11_connections_open_by_default() {
+    check_default_persistence(epoll_config());
+}
+
+#[test]
+fn closes_on_connection_close() {
+    check_connection_close(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_closes_on_connection_close() {
+    check_connection_close(epoll_config());
+}
+
+#[test]
+fn closes_when_handler_sets_connection_close() {
+    check_handler_close(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_closes_when_handler_sets_connection_close() {
+    check_handler_close(epoll_config());
+}
+
+#[test]
+fn closes_after_max_requests() {
+    check_max_requests(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_closes_after_max_requests() {
+    check_max_requests(epoll_config());
+}
+
+fn check_default_persistence(config: ServerConfig) {
+    let (handle, mut stream) = start(&SERVER, config);
+
+    for uri in &["/first", "/second", "/third"] {
+        write!(stream, "GET {} HTTP/1.1\r\n\r\n", uri).unwrap();
+        let response = read_response(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 200 Ok\r\n"));
+        assert!(!response.contains("\r\nConnection:"));
+        assert!(response.ends_with(uri));
+    }
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+// "close" is found in any case and anywhere in the list
+fn check_connection_close(config: ServerConfig) {
+    let (handle, mut stream) = start(&SERVER, config);
+
+    stream
+        .write_all(b"GET /first HTTP/1.1\r\nConnection: Upgrade, CLOSE\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(response.ends_with("/first"));
+    assert!(is_closed(&mut stream));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn check_handler_close(config: ServerConfig) {
+    let (handle, mut stream) = start(&SERVER, config);
+
+    stream.write_all(b"GET /last HTTP/1.1\r\n\r\n").unwrap();
+    let response = read_response(&mut stream);
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(is_closed(&mut stream));
+
+    // The header is only sent once, whichever case the handler used
+    let mut stream = connect(&handle);
+    stream
+        .write_all(b"GET /lowercase HTTP/1.1\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(
+        response
+            .to_ascii_lowercase()
+            .matches("\r\nconnection:")
+            .count(),
+        1
+    );
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(is_closed(&mut stream));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn check_max_requests(mut config: ServerConfig) {
+    config.set_max_requests_per_connection(2);
+    let (handle, mut stream) = start(&SERVER, config);
+
+    stream.write_all(b"GET /first HTTP/1.1\r\n\r\n").unwrap();
+    let response = read_response(&mut stream);
+    assert!(!response.contains("\r\nConnection:"));
+
+    // The last request allowed says so in its response
+    stream.write_all(b"GET /second HTTP/1.1\r\n\r\n").unwrap();
+    let response = read_response(&mut stream);
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(response.ends_with("/second"));
+    assert!(is_closed(&mut stream));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+// A clean close frees the worker right away, even while the client keeps its side open
+#[test]
+fn closing_does_not_hold_worker() {
+    let mut config = ServerConfig::new();
+    config.set_worker_count(1);
+    let (handle, mut first) = start(&SERVER, config);
+
+    first
+        .write_all(b"GET /first HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    read_response(&mut first);
+    assert!(is_closed(&mut first));
+
+    let start = Instant::now();
+    let mut second = connect(&handle);
+    second.write_all(b"GET /second HTTP/1.1\r\n\r\n").unwrap();
+    assert!(read_response(&mut second).ends_with("/second"));
+    assert!(start.elapsed() < Duration::from_millis(250));
+
+    handle.shutdown(Duration::from_secs(1));
+}
@@ -0,0 +1,154 @@
+use http::{Request, Response, Server, ServerConfig, Status};
+use std::{io::Write, time::Duration};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+use common::{read_all, read_response, start};
+
+struct EchoServer;
+
+impl Server for EchoServer {
+    fn handle_request(&self, request: Request) -> Response {
+        Response::new_status(
+            Status::Ok,
+            Some(format!("{} {}", request.header().uri(), request.body())),
+        )
+    }
+}
+
+static SERVER: EchoServer = EchoServer;
+
+fn bodies(responses: &str) -> Vec<&str> {
+    responses
+        .split("HTTP/1.1 ")
+        .skip(1)
+        .map(|response| response.split("\r\n\r\n").nth(1).unwrap())
+        .collect()
+}
+
+#[test]
+fn answers_pipelined_requests_in_order() {
+    check_pipelined_order(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_answers_pipelined_requests_in_order() {
+    check_pipelined_order(epoll_config());
+}
+
+#[test]
+fn closes_after_pipelined_limit() {
+    check_pipelined_limit(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_closes_after_pipelined_limit() {
+    check_pipelined_limit(epoll_config());
+}
+
+#[test]
+fn answers_only_one_request_at_a_time_without_pipelining() {
+    check_no_pipelining(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_answers_only_one_request_at_a_time_without_pipelining() {
+    check_no_pipelining(epoll_config());
+}
+
+#[test]
+fn answers_one_queued_request() {
+    check_one_queued(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_answers_one_queued_request() {
+    check_one_queued(epoll_config());
+}
+
+fn check_pipelined_order(config: ServerConfig) {
+    let (handle, mut stream) = start(&SERVER, config);
+
+    stream
+        .write_all(
+            b"GET /first HTTP/1.1\r\n\r\n\
+            POST /second HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody\
+            POST /third HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n\
+            GET /fourth HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+    let responses = read_all(&mut stream);
+    assert_eq!(
+        bodies(&responses),
+        ["/first ", "/second body", "/third abc", "/fourth "]
+    );
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn check_pipelined_limit(mut config: ServerConfig) {
+    config.set_max_pipelined_requests(2);
+    let (handle, mut stream) = start(&SERVER, config);
+
+    let request = b"GET /request HTTP/1.1\r\n\r\n";
+    stream.write_all(&request.repeat(5)).unwrap();
+
+    // The first request and the two queued behind it are answered
+    let responses = read_all(&mut stream);
+    assert_eq!(bodies(&responses).len(), 3);
+    assert_eq!(responses.matches("Connection: close").count(), 1);
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn check_no_pipelining(mut config: ServerConfig) {
+    config.set_max_pipelined_requests(0);
+    let (handle, mut stream) = start(&SERVER, config);
+
+    // Requests sent one after another keep the connection open
+    for _ in 0..3 {
+        stream.write_all(b"GET /request HTTP/1.1\r\n\r\n").unwrap();
+        let response = read_response(&mut stream);
+        assert!(!response.contains("\r\nConnection:"));
+    }
+
+    // A request queued behind another one goes unanswered
+    stream
+        .write_all(&b"GET /request HTTP/1.1\r\n\r\n".repeat(2))
+        .unwrap();
+    let responses = read_all(&mut stream);
+    assert_eq!(bodies(&responses).len(), 1);
+    assert_eq!(responses.matches("Connection: close").count(), 1);
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn check_one_queued(mut config: ServerConfig) {
+    config.set_max_pipelined_requests(1);
+    let (handle, mut stream) = start(&SERVER, config);
+
+    // Exactly as many queued requests as allowed leave the connection open
+    stream
+        .write_all(&b"GET /request HTTP/1.1\r\n\r\n".repeat(2))
+        .unwrap();
+    for _ in 0..2 {
+        let response = read_response(&mut stream);
+        assert!(!response.contains("\r\nConnection:"));
+    }
+
+    stream
+        .write_all(&b"GET /request HTTP/1.1\r\n\r\n".repeat(3))
+        .unwrap();
+    let responses = read_all(&mut stream);
+    assert_eq!(bodies(&responses).len(), 2);
+    assert_eq!(responses.matches("Connection: close").count(), 1);
+
+    handle.shutdown(Duration::from_secs(1));
+}
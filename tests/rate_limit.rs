@@ -0,0 +1,89 @@
+use http::{
+    RateLimiter, Request, Response, Server, ServerBuilder, Status, TestClient, TestResponse,
+};
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+struct OkServer;
+
+impl Server for OkServer {
+    fn handle_request(&self, _request: Request) -> Response {
+        Response::new_status(Status::Ok, None)
+    }
+}
+
+fn limiter() -> RateLimiter<OkServer> {
+    RateLimiter::new(OkServer, 2, Duration::from_secs(60))
+        .with_key(|request| request.header().get_header("X-Client").map(str::to_owned))
+}
+
+fn send<S: Server>(client: &TestClient<S>, name: &str) -> TestResponse {
+    client.get("/").header("X-Client", name).send()
+}
+
+#[test]
+fn limits_each_client() {
+    let limiter = limiter();
+    let client = TestClient::new(&limiter);
+
+    send(&client, "a")
+        .assert_status(Status::Ok)
+        .assert_header("RateLimit-Limit", "2")
+        .assert_header("RateLimit-Remaining", "1")
+        .assert_header("RateLimit-Reset", "30");
+    send(&client, "a")
+        .assert_status(Status::Ok)
+        .assert_header("RateLimit-Remaining", "0")
+        .assert_header("RateLimit-Reset", "60");
+
+    // A token comes back every 30 seconds
+    let response = send(&client, "a");
+    response
+        .assert_status(Status::TooManyRequests)
+        .assert_header("RateLimit-Remaining", "0")
+        .assert_header("Retry-After", "30");
+
+    // Other clients have buckets of their own
+    send(&client, "b")
+        .assert_status(Status::Ok)
+        .assert_header("RateLimit-Remaining", "1");
+}
+
+#[test]
+fn ignores_requests_without_key() {
+    let limiter = limiter();
+    let client = TestClient::new(&limiter);
+
+    for _ in 0..5 {
+        let response = client.get("/").send();
+        response.assert_status(Status::Ok);
+        assert_eq!(response.header("RateLimit-Limit"), None);
+    }
+}
+
+#[test]
+fn limits_by_peer_address() {
+    let limiter = RateLimiter::new(OkServer, 1, Duration::from_secs(60));
+    let handle = ServerBuilder::new(limiter)
+        .listener(TcpListener::bind("127.0.0.1:0").unwrap())
+        .start()
+        .unwrap();
+
+    let mut stream = TcpStream::connect(handle.local_addrs()[0]).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    let mut responses = String::new();
+    stream.read_to_string(&mut responses).unwrap();
+    assert!(responses.starts_with("HTTP/1.1 200 Ok\r\n"));
+    assert!(responses.contains("HTTP/1.1 429 Too Many Requests\r\n"));
+
+    handle.shutdown(Duration::from_secs(1));
+}
@@ -0,0 +1,117 @@
+use http::{Request, Response, Server, ServerConfig, ServerHandle, Status};
+use std::{
+    io::{Read, Write},
+    net::Shutdown,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+use common::{connect, serve};
+
+struct RecordingServer;
+
+impl Server for RecordingServer {
+    fn handle_request(&self, request: Request) -> Response {
+        if request.header().uri() == "/truncated" {
+            TRUNCATED_HANDLED.store(true, Ordering::SeqCst);
+        }
+
+        Response::new_status(Status::Ok, Some(request.body().to_owned()))
+    }
+}
+
+static SERVER: RecordingServer = RecordingServer;
+static TRUNCATED_HANDLED: AtomicBool = AtomicBool::new(false);
+
+fn start(mut config: ServerConfig) -> ServerHandle {
+    config
+        .set_max_request_line_length(64)
+        .set_max_header_size(256)
+        .set_max_header_count(4)
+        .set_max_body_size(8);
+    serve(&SERVER, config)
+}
+
+// Sends a request, optionally ending the client's side of the connection, and reads until the
+// server closes
+fn send(handle: &ServerHandle, request: &str, end: bool) -> String {
+    let mut stream = connect(handle);
+    stream.write_all(request.as_bytes()).unwrap();
+    if end {
+        stream.shutdown(Shutdown::Write).unwrap();
+    }
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok();
+    response
+}
+
+#[test]
+fn rejects_requests_over_limits() {
+    check_limits(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_rejects_requests_over_limits() {
+    check_limits(epoll_config());
+}
+
+#[test]
+fn never_handles_truncated_bodies() {
+    let handle = start(ServerConfig::new());
+
+    let request = "POST /truncated HTTP/1.1\r\nContent-Length: 8\r\n\r\nabc";
+    let response = send(&handle, request, true);
+    assert!(response.starts_with("HTTP/1.1 400 "));
+    assert!(!TRUNCATED_HANDLED.load(Ordering::SeqCst));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+// The reactor only dispatches complete requests, so a cut off one is dropped with the connection
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_never_handles_truncated_bodies() {
+    let handle = start(epoll_config());
+
+    let request = "POST /truncated HTTP/1.1\r\nContent-Length: 8\r\n\r\nabc";
+    let response = send(&handle, request, true);
+    assert!(!response.starts_with("HTTP/1.1 200 "));
+    assert!(!TRUNCATED_HANDLED.load(Ordering::SeqCst));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn check_limits(config: ServerConfig) {
+    let handle = start(config);
+
+    // Everything exactly at its limit is accepted
+    let uri = format!("/{}", "a".repeat(64 - "POST / HTTP/1.1".len()));
+    let request = format!(
+        "POST {} HTTP/1.1\r\nA: 1\r\nB: 2\r\nConnection: close\r\nContent-Length: 8\r\n\r\n12345678",
+        uri
+    );
+    let response = send(&handle, &request, false);
+    assert!(response.starts_with("HTTP/1.1 200 "));
+    assert!(response.ends_with("\r\n\r\n12345678"));
+
+    // One past each of them is not
+    let request = format!("POST {}a HTTP/1.1\r\n\r\n", uri);
+    assert!(send(&handle, &request, false).starts_with("HTTP/1.1 414 "));
+
+    let request = format!("GET / HTTP/1.1\r\nA: {}\r\n\r\n", "a".repeat(256));
+    assert!(send(&handle, &request, false).starts_with("HTTP/1.1 431 "));
+
+    let request = "GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\nE: 5\r\n\r\n";
+    assert!(send(&handle, request, false).starts_with("HTTP/1.1 431 "));
+
+    let request = "POST / HTTP/1.1\r\nContent-Length: 9\r\n\r\n123456789";
+    assert!(send(&handle, request, false).starts_with("HTTP/1.1 413 "));
+
+    handle.shutdown(Duration::from_secs(1));
+}
@@ -0,0 +1,43 @@
+use http::{serve_connection, Request, Response, Server, ServerConfig, Status};
+
+mod common;
+
+use common::Pipe;
+
+struct EchoServer;
+
+impl Server for EchoServer {
+    fn handle_request(&self, request: Request) -> Response {
+        Response::new_status(
+            Status::Ok,
+            Some(format!("{} {}", request.header().uri(), request.body())),
+        )
+    }
+}
+
+#[test]
+fn serves_requests_until_input_ends() {
+    let mut pipe = Pipe::new(
+        b"GET /first HTTP/1.1\r\n\r\n\
+        POST /second HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody",
+        usize::MAX,
+    );
+    serve_connection(&mut pipe, &EchoServer, &ServerConfig::new()).unwrap();
+
+    let output = String::from_utf8(pipe.output).unwrap();
+    let bodies: Vec<_> = output
+        .split("HTTP/1.1 200 Ok\r\n")
+        .skip(1)
+        .map(|response| response.split("\r\n\r\n").nth(1).unwrap())
+        .collect();
+    assert_eq!(bodies, ["/first ", "/second body"]);
+}
+
+#[test]
+fn answers_malformed_requests() {
+    let mut pipe = Pipe::new(b"GET /missing-version\r\n\r\n", usize::MAX);
+    assert!(serve_connection(&mut pipe, &EchoServer, &ServerConfig::new()).is_err());
+
+    let output = String::from_utf8(pipe.output).unwrap();
+    assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+}
@@ -0,0 +1,100 @@
+use http::{Request, Response, Server, ServerConfig, Status};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+use common::{connect, serve};
+
+struct SlowServer;
+
+impl Server for SlowServer {
+    fn handle_request(&self, request: Request) -> Response {
+        match request.header().uri() {
+            "/slow" => thread::sleep(Duration::from_millis(300)),
+            "/stuck" => thread::sleep(Duration::from_secs(30)),
+            _ => {}
+        }
+
+        Response::new_status(Status::Ok, Some("done".to_owned()))
+    }
+}
+
+static SERVER: SlowServer = SlowServer;
+
+#[test]
+fn shutdown_finishes_in_flight_requests() {
+    check_graceful_shutdown(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_shutdown_finishes_in_flight_requests() {
+    check_graceful_shutdown(epoll_config());
+}
+
+fn check_graceful_shutdown(config: ServerConfig) {
+    let handle = serve(&SERVER, config);
+
+    // One connection waits between requests while another has one in flight
+    let mut idle = connect(&handle);
+    idle.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut response = [0; 256];
+    assert!(idle.read(&mut response).unwrap() > 0);
+
+    let mut busy = connect(&handle);
+    busy.write_all(b"GET /slow HTTP/1.1\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let start = Instant::now();
+    handle.shutdown(Duration::from_secs(5));
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    // The in-flight request is answered and its connection closed after it
+    let mut response = String::new();
+    busy.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 Ok\r\n"));
+    assert!(response.ends_with("\r\n\r\ndone"));
+
+    // The idle connection is closed without waiting out its keep-alive timeout
+    let mut rest = Vec::new();
+    idle.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    // Nothing accepts new clients any more
+    assert!(TcpStream::connect(busy.peer_addr().unwrap()).is_err());
+}
+
+#[test]
+fn shutdown_gives_up_on_stuck_handlers() {
+    check_stuck_handler(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_shutdown_gives_up_on_stuck_handlers() {
+    check_stuck_handler(epoll_config());
+}
+
+fn check_stuck_handler(config: ServerConfig) {
+    let handle = serve(&SERVER, config);
+
+    let mut stuck = connect(&handle);
+    stuck.write_all(b"GET /stuck HTTP/1.1\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    // Shutdown returns once the deadline passes, closing the connection under the handler
+    let start = Instant::now();
+    handle.shutdown(Duration::from_millis(200));
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    let mut response = Vec::new();
+    stuck.read_to_end(&mut response).ok();
+    assert!(response.is_empty());
+}
@@ -0,0 +1,131 @@
+use http::{Request, Response, Server, ServerBuilder, ServerConfig, Status, StreamingBody};
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+use common::{read_all, start};
+
+const PIECE_SIZE: usize = 64 * 1024;
+const BODY_SIZE: usize = 64 * 1024 * 1024;
+
+// Streams a large body, counting how much of it has been produced
+struct LargeServer {
+    produced: Arc<AtomicUsize>,
+}
+
+impl Server for LargeServer {
+    fn handle_request(&self, _request: Request) -> Response {
+        let produced = self.produced.clone();
+        Response::new_stream(
+            Status::Ok,
+            StreamingBody::new(move |writer| {
+                let piece = vec![b'a'; PIECE_SIZE];
+                for _ in 0..BODY_SIZE / PIECE_SIZE {
+                    writer.write_all(&piece)?;
+                    produced.fetch_add(PIECE_SIZE, Ordering::SeqCst);
+                }
+                Ok(())
+            }),
+        )
+    }
+}
+
+// Streams a short body in pieces
+struct PiecesServer;
+
+impl Server for PiecesServer {
+    fn handle_request(&self, _request: Request) -> Response {
+        Response::new_stream(
+            Status::Ok,
+            StreamingBody::new(|writer| {
+                writer.write_all(b"first ")?;
+                writer.write_all(b"second")
+            }),
+        )
+    }
+}
+
+#[test]
+fn streams_with_backpressure() {
+    check_backpressure(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_streams_with_backpressure() {
+    check_backpressure(epoll_config());
+}
+
+fn check_backpressure(config: ServerConfig) {
+    let produced = Arc::new(AtomicUsize::new(0));
+    let handle = ServerBuilder::new(LargeServer {
+        produced: produced.clone(),
+    })
+    .listener(TcpListener::bind("127.0.0.1:0").unwrap())
+    .config(config)
+    .start()
+    .unwrap();
+
+    let mut stream = TcpStream::connect(handle.local_addrs()[0]).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    // A client that isn't reading holds the producer back once the socket buffers fill up
+    thread::sleep(Duration::from_millis(300));
+    let held_back = produced.load(Ordering::SeqCst);
+    assert!(held_back < BODY_SIZE / 4, "produced {} bytes", held_back);
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    assert!(response.starts_with(b"HTTP/1.1 200 Ok\r\n"));
+    assert!(response.ends_with(b"\r\n0\r\n\r\n"));
+    assert!(response.len() > BODY_SIZE);
+    assert_eq!(produced.load(Ordering::SeqCst), BODY_SIZE);
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+#[test]
+fn streams_to_http10_until_close() {
+    check_http10_stream(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_streams_to_http10_until_close() {
+    check_http10_stream(epoll_config());
+}
+
+fn check_http10_stream(config: ServerConfig) {
+    let (handle, mut stream) = start(PiecesServer, config);
+
+    // HTTP/1.0 clients can't decode chunks, so the body runs until the server closes, even if the
+    // client asked to keep the connection
+    stream
+        .write_all(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n")
+        .unwrap();
+    let response = read_all(&mut stream);
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 Ok\r\n"));
+    assert!(head.contains("\r\nConnection: close"));
+    assert!(!head.contains("Transfer-Encoding"));
+    assert!(!head.contains("Content-Length"));
+    assert_eq!(body, "first second");
+
+    handle.shutdown(Duration::from_secs(1));
+}
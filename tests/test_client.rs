@@ -0,0 +1,96 @@
+use http::{Method, Request, Response, Server, Status, StreamingBody, TestClient};
+use std::io::Write;
+
+struct ApiServer;
+
+impl Server for ApiServer {
+    fn handle_request(&self, request: Request) -> Response {
+        match (request.header().method(), request.header().uri()) {
+            (Method::Get, "/greeting") => {
+                let name = request.header().get_header("X-Name").unwrap_or("world");
+                let mut response =
+                    Response::new_status(Status::Ok, Some(format!("Hello, {}!", name)));
+                response
+                    .header_mut()
+                    .insert_header("X-Greeted".to_owned(), name.to_owned());
+                response
+            }
+            (Method::Post, "/echo") => {
+                Response::new_status(Status::Created, Some(request.body().to_owned()))
+            }
+            (Method::Get, "/stream") => Response::new_stream(
+                Status::Ok,
+                StreamingBody::new(|writer| {
+                    writer.write_all(b"first ")?;
+                    writer.write_all(b"second")?;
+                    writer.set_trailer("X-Checksum".to_owned(), "abc".to_owned());
+                    Ok(())
+                }),
+            ),
+            (Method::Get, "/panic") => panic!("handler failed"),
+            _ => Response::new_status(Status::NotFound, None),
+        }
+    }
+}
+
+#[test]
+fn sends_requests_through_the_server() {
+    let client = TestClient::new(&ApiServer);
+
+    client
+        .get("/greeting")
+        .header("X-Name", "tester")
+        .send()
+        .assert_status(Status::Ok)
+        .assert_header("x-greeted", "tester")
+        .assert_header("Content-Type", "text/plain")
+        .assert_body("Hello, tester!");
+
+    client
+        .post("/echo")
+        .body("payload")
+        .send()
+        .assert_status(Status::Created)
+        .assert_body("payload");
+
+    client
+        .delete("/greeting")
+        .send()
+        .assert_status(Status::NotFound);
+}
+
+#[test]
+fn decodes_streamed_responses() {
+    let response = TestClient::new(&ApiServer).get("/stream").send();
+
+    response
+        .assert_status(Status::Ok)
+        .assert_header("Transfer-Encoding", "chunked")
+        .assert_body("first second");
+    assert_eq!(response.trailer("X-Checksum"), Some("abc"));
+}
+
+#[test]
+fn sends_raw_requests() {
+    let response = TestClient::new(&ApiServer).send_raw(b"GET /greeting\r\n\r\n");
+    assert_eq!(response.status_code(), 400);
+    assert_eq!(response.header("Connection"), Some("close"));
+}
+
+#[test]
+fn answers_handler_panics() {
+    TestClient::new(&ApiServer)
+        .get("/panic")
+        .send()
+        .assert_status(Status::InternalServerError)
+        .assert_header("Connection", "close");
+}
+
+#[test]
+#[should_panic(expected = "Unexpected status 404")]
+fn reports_unexpected_status() {
+    TestClient::new(&ApiServer)
+        .get("/missing")
+        .send()
+        .assert_status(Status::Ok);
+}
@@ -0,0 +1,130 @@
+use http::{Request, Response, Server, ServerConfig, ServerHandle, Status};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::epoll_config;
+
+struct OkServer;
+
+impl Server for OkServer {
+    fn handle_request(&self, _request: Request) -> Response {
+        Response::new_status(Status::Ok, Some("done".to_owned()))
+    }
+}
+
+static SERVER: OkServer = OkServer;
+
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+fn start(mut config: ServerConfig) -> (ServerHandle, TcpStream) {
+    config
+        .set_header_read_timeout(Some(TIMEOUT))
+        .set_body_read_timeout(Some(TIMEOUT))
+        .set_keep_alive_timeout(Some(TIMEOUT));
+    common::start(&SERVER, config)
+}
+
+// Reads until the server closes the connection, which has to happen well before the client's
+// own read timeout
+fn read_until_closed(stream: &mut TcpStream) -> String {
+    let start = Instant::now();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    String::from_utf8(response).unwrap()
+}
+
+#[test]
+fn closes_silent_connections() {
+    check_silent_connection(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_closes_silent_connections() {
+    check_silent_connection(epoll_config());
+}
+
+#[test]
+fn times_out_partial_headers() {
+    check_partial_header(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_times_out_partial_headers() {
+    check_partial_header(epoll_config());
+}
+
+#[test]
+fn times_out_partial_bodies() {
+    check_partial_body(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_times_out_partial_bodies() {
+    check_partial_body(epoll_config());
+}
+
+#[test]
+fn closes_idle_keep_alive_connections() {
+    check_keep_alive_timeout(ServerConfig::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn epoll_closes_idle_keep_alive_connections() {
+    check_keep_alive_timeout(epoll_config());
+}
+
+// A client that never sends anything isn't owed a response
+fn check_silent_connection(config: ServerConfig) {
+    let (handle, mut stream) = start(config);
+
+    assert_eq!(read_until_closed(&mut stream), "");
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn check_partial_header(config: ServerConfig) {
+    let (handle, mut stream) = start(config);
+
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: ex").unwrap();
+    let response = read_until_closed(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 408 "));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+fn check_partial_body(config: ServerConfig) {
+    let (handle, mut stream) = start(config);
+
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc")
+        .unwrap();
+    let response = read_until_closed(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 408 "));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+// Once a response is sent, waiting for the next request ends quietly with the timeout
+fn check_keep_alive_timeout(config: ServerConfig) {
+    let (handle, mut stream) = start(config);
+
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let response = read_until_closed(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 200 Ok\r\n"));
+    assert!(response.ends_with("\r\n\r\ndone"));
+    assert!(!response.contains("Connection: close"));
+
+    handle.shutdown(Duration::from_secs(1));
+}
@@ -0,0 +1,207 @@
+#![cfg(feature = "tls")]
+
+use http::{
+    Request, Response, Server, ServerBuilder, ServerConfig, ServerHandle, Status, TlsConfig,
+};
+use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, IsCa, KeyPair};
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+};
+use std::{
+    convert::TryFrom,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+struct TlsEchoServer;
+
+impl Server for TlsEchoServer {
+    fn handle_request(&self, request: Request) -> Response {
+        let tls = request.tls().unwrap();
+        Response::new_status(
+            Status::Ok,
+            Some(format!(
+                "{} {} {}",
+                tls.server_name().unwrap_or("-"),
+                String::from_utf8_lossy(tls.alpn_protocol().unwrap_or(b"-")),
+                tls.peer_certificates().len()
+            )),
+        )
+    }
+}
+
+static SERVER: TlsEchoServer = TlsEchoServer;
+
+// A certificate and key written out as PEM files
+struct Identity {
+    certificate: CertificateDer<'static>,
+    key: PrivateKeyDer<'static>,
+    cert_file: PathBuf,
+    key_file: PathBuf,
+}
+
+impl Identity {
+    fn self_signed(name: &str) -> Self {
+        let key = KeyPair::generate().unwrap();
+        let certificate = CertificateParams::new(vec![name.to_owned()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        Identity::write(name, certificate.pem(), certificate.der().clone(), key)
+    }
+
+    fn write(name: &str, pem: String, certificate: CertificateDer<'static>, key: KeyPair) -> Self {
+        let directory = std::env::temp_dir();
+        let prefix = format!("http-tls-{}-{}", std::process::id(), name);
+        let cert_file = directory.join(format!("{}.crt", prefix));
+        let key_file = directory.join(format!("{}.key", prefix));
+        std::fs::write(&cert_file, pem).unwrap();
+        std::fs::write(&key_file, key.serialize_pem()).unwrap();
+
+        Identity {
+            certificate,
+            key: PrivateKeyDer::try_from(key.serialize_der()).unwrap(),
+            cert_file,
+            key_file,
+        }
+    }
+}
+
+fn start(tls: &TlsConfig) -> ServerHandle {
+    let mut config = ServerConfig::new();
+    config.set_tls(tls).unwrap();
+
+    ServerBuilder::new(&SERVER)
+        .listener(TcpListener::bind("127.0.0.1:0").unwrap())
+        .config(config)
+        .start()
+        .unwrap()
+}
+
+fn client_config(trusted: &Identity, client: Option<&Identity>, alpn: &[&[u8]]) -> ClientConfig {
+    let mut roots = RootCertStore::empty();
+    roots.add(trusted.certificate.clone()).unwrap();
+
+    let builder =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+
+    let mut config = match client {
+        Some(client) => builder
+            .with_client_auth_cert(vec![client.certificate.clone()], client.key.clone_key())
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+    config
+}
+
+fn get(handle: &ServerHandle, server_name: &str, config: ClientConfig) -> std::io::Result<String> {
+    let stream = TcpStream::connect(handle.local_addrs()[0]).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    let server_name = ServerName::try_from(server_name.to_owned()).unwrap();
+    let connection = ClientConnection::new(Arc::new(config), server_name).unwrap();
+    let mut stream = StreamOwned::new(connection, stream);
+
+    stream.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+fn body(response: &str) -> &str {
+    response.split("\r\n\r\n").nth(1).unwrap()
+}
+
+#[test]
+fn serves_requests_over_tls() {
+    let identity = Identity::self_signed("localhost");
+    let handle =
+        start(&TlsConfig::from_pem_files(&identity.cert_file, &identity.key_file).unwrap());
+
+    let response = get(&handle, "localhost", client_config(&identity, None, &[])).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 Ok\r\n"));
+    assert_eq!(body(&response), "localhost - 0");
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+#[test]
+fn selects_certificate_by_server_name() {
+    let default = Identity::self_signed("default.test");
+    let other = Identity::self_signed("other.test");
+
+    let mut tls = TlsConfig::from_pem_files(&default.cert_file, &default.key_file).unwrap();
+    tls.add_sni_certificate("other.test", &other.cert_file, &other.key_file)
+        .unwrap();
+    let handle = start(&tls);
+
+    // Each client only trusts the certificate it expects
+    let response = get(&handle, "other.test", client_config(&other, None, &[])).unwrap();
+    assert_eq!(body(&response), "other.test - 0");
+
+    let response = get(&handle, "default.test", client_config(&default, None, &[])).unwrap();
+    assert_eq!(body(&response), "default.test - 0");
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+#[test]
+fn reports_negotiated_alpn_protocol() {
+    let identity = Identity::self_signed("localhost");
+    let mut tls = TlsConfig::from_pem_files(&identity.cert_file, &identity.key_file).unwrap();
+    tls.set_alpn_protocols(vec![b"h2".to_vec(), b"http/1.1".to_vec()]);
+    let handle = start(&tls);
+
+    let config = client_config(&identity, None, &[b"http/1.1"]);
+    let response = get(&handle, "localhost", config).unwrap();
+    assert_eq!(body(&response), "localhost http/1.1 0");
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+#[test]
+fn verifies_client_certificates() {
+    let server = Identity::self_signed("localhost");
+
+    let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate().unwrap()).unwrap();
+    let ca_file = std::env::temp_dir().join(format!("http-tls-{}-ca.crt", std::process::id()));
+    std::fs::write(&ca_file, ca.pem()).unwrap();
+
+    let client_key = KeyPair::generate().unwrap();
+    let client_certificate = CertificateParams::new(vec!["client".to_owned()])
+        .unwrap()
+        .signed_by(&client_key, &ca)
+        .unwrap();
+    let client = Identity::write(
+        "client",
+        client_certificate.pem(),
+        client_certificate.der().clone(),
+        client_key,
+    );
+
+    let mut tls = TlsConfig::from_pem_files(&server.cert_file, &server.key_file).unwrap();
+    tls.set_client_ca_file(&ca_file, true).unwrap();
+    let handle = start(&tls);
+
+    let config = client_config(&server, Some(&client), &[]);
+    let response = get(&handle, "localhost", config).unwrap();
+    assert_eq!(body(&response), "localhost - 1");
+
+    // Clients without a certificate are turned away during the handshake
+    assert!(get(&handle, "localhost", client_config(&server, None, &[])).is_err());
+
+    handle.shutdown(Duration::from_secs(1));
+}
@@ -0,0 +1,246 @@
+#![cfg(unix)]
+
+use http::{Request, Response, Server, ServerBuilder, ServerHandle, Status};
+use std::{
+    io::{ErrorKind, Read, Write},
+    os::unix::{
+        fs::{MetadataExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+struct CredentialsServer;
+
+impl Server for CredentialsServer {
+    fn handle_request(&self, request: Request) -> Response {
+        let body = match request.peer_credentials() {
+            Some(credentials) => format!(
+                "{} {} {}",
+                credentials.uid(),
+                credentials.gid(),
+                credentials.pid()
+            ),
+            None => "-".to_owned(),
+        };
+
+        Response::new_status(Status::Ok, Some(body))
+    }
+}
+
+static SERVER: CredentialsServer = CredentialsServer;
+
+fn socket_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("http-unix-{}-{}.sock", std::process::id(), name));
+    std::fs::remove_file(&path).ok();
+    path
+}
+
+fn get(path: &PathBuf) -> String {
+    let mut stream = UnixStream::connect(path).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+fn body(response: &str) -> &str {
+    response.split("\r\n\r\n").nth(1).unwrap()
+}
+
+fn start(path: &PathBuf) -> ServerHandle {
+    ServerBuilder::new(&SERVER).bind_unix(path).start().unwrap()
+}
+
+#[test]
+fn serves_requests_over_unix_socket() {
+    let path = socket_path("serve");
+    let handle = ServerBuilder::new(&SERVER)
+        .bind_unix(&path)
+        .unix_socket_mode(0o600)
+        .start()
+        .unwrap();
+
+    let metadata = std::fs::metadata(&path).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+    let response = get(&path);
+    assert!(response.starts_with("HTTP/1.1 200 Ok\r\n"));
+
+    // The client is this process, which also owns the socket file
+    if cfg!(target_os = "linux") {
+        assert_eq!(
+            body(&response),
+            format!(
+                "{} {} {}",
+                metadata.uid(),
+                metadata.gid(),
+                std::process::id()
+            )
+        );
+    }
+
+    // The socket file goes away with the server
+    handle.shutdown(Duration::from_secs(1));
+    assert!(!path.exists());
+}
+
+#[test]
+fn serves_bound_unix_listener() {
+    let path = socket_path("listener");
+    let handle = ServerBuilder::new(&SERVER)
+        .unix_listener(UnixListener::bind(&path).unwrap())
+        .start()
+        .unwrap();
+
+    assert!(get(&path).starts_with("HTTP/1.1 200 Ok\r\n"));
+
+    handle.shutdown(Duration::from_secs(1));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replaces_stale_socket_file() {
+    let path = socket_path("stale");
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let handle = start(&path);
+    assert!(get(&path).starts_with("HTTP/1.1 200 Ok\r\n"));
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+#[test]
+fn keeps_socket_of_running_server() {
+    let path = socket_path("running");
+    let _listener = UnixListener::bind(&path).unwrap();
+
+    let error = ServerBuilder::new(&SERVER)
+        .bind_unix(&path)
+        .start()
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), ErrorKind::AddrInUse);
+    assert!(path.exists());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn keeps_files_that_are_not_sockets() {
+    let path = socket_path("file");
+    std::fs::write(&path, "data").unwrap();
+
+    let error = ServerBuilder::new(&SERVER)
+        .bind_unix(&path)
+        .start()
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "data");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn removes_socket_files_when_start_fails() {
+    let bound = socket_path("bound");
+    let blocked = socket_path("blocked");
+    std::fs::write(&blocked, "data").unwrap();
+
+    ServerBuilder::new(&SERVER)
+        .bind_unix(&bound)
+        .bind_unix(&blocked)
+        .start()
+        .err()
+        .unwrap();
+    assert!(!bound.exists());
+
+    std::fs::remove_file(&blocked).unwrap();
+}
+
+#[test]
+fn leaves_socket_bound_by_another_server() {
+    let path = socket_path("rebound");
+    let first = start(&path);
+
+    // Another server takes over the path while the first still runs
+    std::fs::remove_file(&path).unwrap();
+    let second = start(&path);
+
+    first.shutdown(Duration::from_secs(1));
+    assert!(get(&path).starts_with("HTTP/1.1 200 Ok\r\n"));
+
+    second.shutdown(Duration::from_secs(1));
+    assert!(!path.exists());
+}
+
+#[test]
+fn reports_credentials_on_every_request() {
+    let path = socket_path("credentials");
+    let handle = start(&path);
+
+    let mut stream = UnixStream::connect(&path).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let bodies: Vec<_> = response.split("HTTP/1.1 ").skip(1).map(body).collect();
+    assert_eq!(bodies.len(), 2);
+    assert_eq!(bodies[0], bodies[1]);
+    if cfg!(target_os = "linux") {
+        assert_ne!(bodies[0], "-");
+    }
+
+    handle.shutdown(Duration::from_secs(1));
+}
+
+// Binds staged in the same directory at once, or after a crashed process with the same id left
+// its staging directories behind, don't get in each other's way
+#[test]
+fn stages_binds_with_mode_separately() {
+    let dir = std::env::temp_dir().join(format!("http-unix-{}-staging", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir(&dir).unwrap();
+    for count in 0..32 {
+        let leftover = dir.join(format!(".bind-{}-{}", std::process::id(), count));
+        std::fs::create_dir(&leftover).unwrap();
+        UnixListener::bind(leftover.join("socket")).unwrap();
+    }
+
+    let binds: Vec<_> = (0..4)
+        .map(|index| {
+            let path = dir.join(format!("{}.sock", index));
+            thread::spawn(move || {
+                let handle = ServerBuilder::new(&SERVER)
+                    .bind_unix(&path)
+                    .unix_socket_mode(0o600)
+                    .start()
+                    .unwrap();
+                (handle, path)
+            })
+        })
+        .collect();
+
+    for bind in binds {
+        let (handle, path) = bind.join().unwrap();
+        assert!(get(&path).starts_with("HTTP/1.1 200 Ok\r\n"));
+        handle.shutdown(Duration::from_secs(1));
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}